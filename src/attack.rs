@@ -0,0 +1,195 @@
+use crate::hash::HashSpec;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::error::Error;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Two different strings with the same hash, together with some statistics about the search.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Collision {
+    pub first: String,
+    pub second: String,
+    /// Common value of the strings under every hash of the target.
+    pub hashes: Vec<u64>,
+    /// Name of the attack which found the collision.
+    pub attack: &'static str,
+    /// Length of the strings, in characters.
+    pub length: usize,
+    /// For every attacked hash, the tree depth, the birthday length or the lattice length reached.
+    pub depths: Vec<usize>,
+    /// Number of samples drawn, heap pops or basis swaps performed.
+    pub steps: u64,
+    pub elapsed: Duration,
+}
+
+/// Reason why an attack failed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AttackError {
+    /// The alphabet has fewer than two distinct words.
+    AlphabetTooSmall,
+    /// The words of the alphabet are empty or don't have the same length.
+    InvalidAlphabet,
+    /// The parameters of the hash can't be handled by the attack.
+    InvalidHash(String),
+    /// The parameters of the attack are not valid.
+    InvalidParameter(String),
+    /// The requested length is shorter than the `min_length` characters needed by the attack.
+    LengthTooShort { min_length: usize },
+    /// No collision was found using strings of up to `max_length` words.
+    LengthLimitExceeded { attack: &'static str, max_length: usize },
+    /// The attack didn't finish before the deadline of the context.
+    Timeout,
+    /// The attack needs about `required` bytes, more than the `limit` of the context.
+    MemoryLimitExceeded { required: usize, limit: usize },
+    /// A thread running part of the attack panicked.
+    WorkerFailed,
+}
+
+impl fmt::Display for AttackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AttackError::AlphabetTooSmall => write!(f, "the alphabet must contain at least two different words"),
+            AttackError::InvalidAlphabet => {
+                write!(f, "words of the alphabet must be non-empty and have the same length")
+            }
+            AttackError::InvalidHash(reason) => write!(f, "invalid hash: {}", reason),
+            AttackError::InvalidParameter(reason) => write!(f, "invalid parameter: {}", reason),
+            AttackError::LengthLimitExceeded { attack, max_length } => {
+                write!(f, "{} attack found no collision with strings of up to {} words", attack, max_length)
+            }
+            AttackError::LengthTooShort { min_length } => {
+                write!(f, "strings must have at least {} characters", min_length)
+            }
+            AttackError::Timeout => write!(f, "time limit exceeded"),
+            AttackError::MemoryLimitExceeded { required, limit } => {
+                write!(f, "memory limit exceeded: {} bytes needed, {} allowed", required, limit)
+            }
+            AttackError::WorkerFailed => write!(f, "a worker thread of the attack failed"),
+        }
+    }
+}
+
+impl Error for AttackError {}
+
+/// Checks that `alphabet` is made of at least two different words with the same length.
+pub(crate) fn check_alphabet(alphabet: &[String]) -> Result<(), AttackError> {
+    let len = match alphabet.first() {
+        Some(w) => w.chars().count(),
+        None => return Err(AttackError::AlphabetTooSmall),
+    };
+    if len == 0 || alphabet.iter().any(|w| w.chars().count() != len) {
+        return Err(AttackError::InvalidAlphabet);
+    }
+    if alphabet.iter().all(|w| *w == alphabet[0]) {
+        return Err(AttackError::AlphabetTooSmall);
+    }
+    Ok(())
+}
+
+/// Snapshot of a running attack, reported to the observer of the [`Context`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Progress {
+    /// The tree attack on `2^depth` words filled `fill` nodes of the `cluster`-th of the
+    /// `clusters` clusters merged at `phase`. `best` is the smallest non-zero sum seen so far.
+    Tree {
+        depth: usize,
+        phase: usize,
+        cluster: usize,
+        clusters: usize,
+        fill: usize,
+        best: Option<i128>,
+    },
+    /// The birthday attack drew `samples` strings of `length` words.
+    Birthday { length: usize, samples: u64 },
+    /// The lattice attack on strings of `length` characters performed `swaps` basis swaps.
+    Lattice { length: usize, swaps: u64 },
+}
+
+/// Callback receiving the progress of the attacks.
+pub type Observer = Box<dyn FnMut(&Progress)>;
+
+/// State shared by the attacks during a run.
+pub struct Context {
+    seed: u64,
+    pub(crate) rng: ChaCha8Rng,
+    observer: Option<Observer>,
+    deadline: Option<Instant>,
+    max_memory: Option<usize>,
+}
+
+impl Context {
+    /// Creates a context whose random choices are determined by `seed`, with a fixed generator so
+    /// that the same seed gives the same strings across versions. Only the distinguished points
+    /// birthday attack with several threads depends on their scheduling.
+    pub fn new(seed: u64) -> Context {
+        Context {
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            observer: None,
+            deadline: None,
+            max_memory: None,
+        }
+    }
+
+    /// Calls `observer` every time an attack reports its progress.
+    pub fn with_observer<F: FnMut(&Progress) + 'static>(mut self, observer: F) -> Context {
+        self.observer = Some(Box::new(observer));
+        self
+    }
+
+    /// Makes the attacks fail with [`AttackError::Timeout`] after `timeout` from now.
+    pub fn with_timeout(mut self, timeout: Duration) -> Context {
+        self.deadline = Some(Instant::now() + timeout);
+        self
+    }
+
+    /// Makes the attacks fail with [`AttackError::MemoryLimitExceeded`] instead of allocating
+    /// more than `bytes` bytes.
+    pub fn with_max_memory(mut self, bytes: usize) -> Context {
+        self.max_memory = Some(bytes);
+        self
+    }
+
+    /// Seed used to initialize the random number generator.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub(crate) fn check_time(&self) -> Result<(), AttackError> {
+        match self.deadline {
+            Some(deadline) if Instant::now() >= deadline => Err(AttackError::Timeout),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn check_memory(&self, required: usize) -> Result<(), AttackError> {
+        match self.max_memory {
+            Some(limit) if required > limit => Err(AttackError::MemoryLimitExceeded { required, limit }),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn report(&mut self, progress: Progress) {
+        if let Some(observer) = &mut self.observer {
+            observer(&progress);
+        }
+    }
+}
+
+impl Default for Context {
+    /// Creates a context with a random seed.
+    fn default() -> Context {
+        Context::new(rand::thread_rng().gen())
+    }
+}
+
+/// Common interface of all the attacks, so that they can be chosen at runtime.
+pub trait Attack {
+    /// Short name of the attack.
+    fn name(&self) -> &'static str;
+
+    /// Finds two different strings with the same hash under every hash of `target`, drawing
+    /// random choices from `ctx`.
+    fn run(&self, target: &HashSpec, ctx: &mut Context) -> Result<Collision, AttackError>;
+}
@@ -1,56 +1,386 @@
-use rand::Rng;
+use crate::attack::{check_alphabet, Attack, AttackError, Collision, Context, Progress};
+use crate::hash::{check_arithmetic, HashSpec, PolynomialHash};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 use std::collections::HashMap;
+use std::mem::size_of;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
 
-fn gen_string(len: u64, alphabet: &Vec<String>) -> String {
-    let mut rng = rand::thread_rng();
+/// How the birthday attack looks for two strings with the same hash.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Strategy {
+    /// Stores `sqrt(modulus)` random strings, feasible for small moduli.
+    #[default]
+    Table,
+    /// Walks `h -> hash(string(h))` until reaching distinguished points, with constant memory per
+    /// walk, feasible for moduli up to `2^64`.
+    DistinguishedPoints,
+}
+
+/// Largest number of values for which the table strategy is used by default, storing about
+/// `2^22` samples.
+pub const MAX_TABLE_VALUES: u128 = 1 << 44;
+
+impl Strategy {
+    /// Returns the table strategy if it fits all `hashes`, distinguished points otherwise.
+    pub fn for_hashes(hashes: &[PolynomialHash]) -> Strategy {
+        if hashes.iter().all(|h| h.values() <= MAX_TABLE_VALUES) {
+            Strategy::Table
+        } else {
+            Strategy::DistinguishedPoints
+        }
+    }
+}
+
+/// Birthday attack.
+#[derive(Clone, Debug)]
+pub struct BirthdayAttack {
+    /// Words used to build the strings, they must have the same length.
+    pub alphabet: Vec<String>,
+    pub strategy: Strategy,
+    /// Number of parallel walks of the distinguished points strategy. With more than one, the
+    /// collision found depends on the scheduling of the threads, not only on the seed.
+    pub threads: usize,
+}
+
+impl Default for BirthdayAttack {
+    fn default() -> BirthdayAttack {
+        BirthdayAttack {
+            alphabet: crate::default_alphabet(),
+            strategy: Strategy::default(),
+            threads: 1,
+        }
+    }
+}
+
+impl Attack for BirthdayAttack {
+    fn name(&self) -> &'static str {
+        "birthday"
+    }
+
+    fn run(&self, target: &HashSpec, ctx: &mut Context) -> Result<Collision, AttackError> {
+        match self.strategy {
+            Strategy::Table => find_collision(&target.hashes, self.alphabet.clone(), ctx),
+            Strategy::DistinguishedPoints => {
+                find_collision_distinguished(&target.hashes, self.alphabet.clone(), self.threads, ctx)
+            }
+        }
+    }
+}
+
+fn gen_string<R: Rng>(len: u64, alphabet: &[String], rng: &mut R) -> String {
     let mut word = String::new();
     for _ in 0..len {
         let idx = rng.gen_range(0, alphabet.len());
         word.push_str(&alphabet[idx]);
     }
-    return word;
+    word
 }
 
-fn get_hash(word: &String, base: u64, module: u64) -> u64 {
-    let mut res = 0;
-    for c in word.chars() {
-        res = (res * base + c as u64) % module;
-    }
-    return res;
+const MIN_LENGTH: u64 = 6;
+const MAX_LENGTH: u64 = 64;
+const CHECK_INTERVAL: u64 = 1 << 10;
+const REPORT_INTERVAL: u64 = 1 << 16;
+const REPORT_PERIOD: Duration = Duration::from_millis(100);
+/// Hashes with fewer values draw one more sample than values, so that two of them collide.
+const MIN_SAMPLES: usize = 1 << 10;
+/// Hashes with fewer values leave no room for distinct walks, the table strategy is used instead.
+const MIN_WALK_VALUES: u128 = 1 << 16;
+
+struct SingleCollision {
+    first: String,
+    second: String,
+    length: usize,
+    samples: u64,
 }
 
 fn find_single_collision(
-    base: u64, module: u64, alphabet: &Vec<String>,
-) -> Option<(String, String)> {
-    let bound = (module as f64).sqrt() as usize;
-    let mut samples = HashMap::with_capacity(bound);
-    for len in 6..64 {
+    hash: &PolynomialHash, alphabet: &[String], ctx: &mut Context,
+) -> Result<SingleCollision, AttackError> {
+    if hash.values() > MAX_TABLE_VALUES {
+        let reason = format!("{} values need too many samples, use distinguished points", hash.values());
+        return Err(AttackError::InvalidParameter(reason));
+    }
+    let bound = ((hash.values() as f64).sqrt() as usize).max((hash.values() as usize + 1).min(MIN_SAMPLES));
+    let word_bytes = alphabet.iter().map(|w| w.len()).max().unwrap_or(0);
+    let mut samples = HashMap::new();
+    let mut drawn = 0;
+    for len in MIN_LENGTH..MAX_LENGTH {
+        ctx.check_memory(bound.saturating_mul(size_of::<(u64, String)>() + 1 + len as usize * word_bytes))?;
         samples.clear();
+        samples.reserve(bound);
         for _ in 0..bound {
-            let word = gen_string(len, alphabet);
-            let hash = get_hash(&word, base, module);
-            if let Some(coll) = samples.insert(hash, word.clone()) {
+            let word = gen_string(len, alphabet, &mut ctx.rng);
+            let value = hash.evaluate(&word);
+            drawn += 1;
+            if drawn % CHECK_INTERVAL == 0 {
+                ctx.check_time()?;
+            }
+            if drawn % REPORT_INTERVAL == 0 {
+                ctx.report(Progress::Birthday {
+                    length: len as usize,
+                    samples: drawn,
+                });
+            }
+            if let Some(coll) = samples.insert(value, word.clone()) {
                 if word != coll {
-                    return Some((word, coll));
+                    return Ok(SingleCollision {
+                        first: word,
+                        second: coll,
+                        length: len as usize,
+                        samples: drawn,
+                    });
                 }
             }
         }
+        ctx.report(Progress::Birthday {
+            length: len as usize,
+            samples: drawn,
+        });
     }
-    None
+    Err(AttackError::LengthLimitExceeded {
+        attack: "birthday",
+        max_length: MAX_LENGTH as usize - 1,
+    })
 }
 
-pub fn find_collision(
-    bases: Vec<u64>, modules: Vec<u64>, init_alphabet: Vec<String>,
-) -> Option<(String, String)> {
-    let mut alphabet = init_alphabet;
-    for (&b, &m) in bases.iter().zip(modules.iter()) {
-        if let Some((fi, se)) = find_single_collision(b, m, &alphabet) {
-            alphabet = vec![fi, se];
+/// Deterministic walk `x -> hash(string(x))` over the values of a hash.
+struct Walker<'a> {
+    hash: &'a PolynomialHash,
+    alphabet: &'a [String],
+    /// Number of words of the strings, enough for the map from values to strings to be almost
+    /// injective.
+    length: usize,
+    salt: u64,
+    /// Values whose `distinguished_bits` lowest bits are zero end the walks.
+    distinguished_bits: u32,
+    max_steps: u64,
+}
+
+#[derive(Default)]
+struct SharedWalks {
+    /// Start and number of steps of the walk reaching each distinguished point.
+    points: Mutex<HashMap<u64, (u64, u64)>>,
+    steps: AtomicU64,
+    stop: AtomicBool,
+}
+
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+impl<'a> Walker<'a> {
+    fn new(hash: &'a PolynomialHash, alphabet: &'a [String], salt: u64) -> Walker<'a> {
+        let values = hash.values();
+        let (mut length, mut strings) = (0, 1u128);
+        while strings < values << 8 {
+            strings = strings.saturating_mul(alphabet.len() as u128);
+            length += 1;
+        }
+        let distinguished_bits = (128 - (values - 1).leading_zeros()) / 4;
+        Walker {
+            hash,
+            alphabet,
+            length,
+            salt,
+            distinguished_bits,
+            max_steps: 20 << distinguished_bits,
+        }
+    }
+
+    fn string(&self, x: u64) -> String {
+        let mut state = x ^ self.salt;
+        let mut word = String::new();
+        for _ in 0..self.length {
+            let idx = splitmix64(&mut state) % self.alphabet.len() as u64;
+            word.push_str(&self.alphabet[idx as usize]);
+        }
+        word
+    }
+
+    fn step(&self, x: u64) -> u64 {
+        self.hash.evaluate(&self.string(x))
+    }
+
+    fn is_distinguished(&self, x: u64) -> bool {
+        x.trailing_zeros() >= self.distinguished_bits
+    }
+
+    fn start<R: Rng>(&self, rng: &mut R) -> u64 {
+        match self.hash.values() {
+            values if values > u64::MAX as u128 => rng.gen(),
+            values => rng.gen_range(0, values as u64),
+        }
+    }
+
+    /// Walks from `a` and `b`, which reach the same distinguished point after `len_a` and `len_b`
+    /// steps, until the two walks merge.
+    fn merge(&self, (mut a, len_a): (u64, u64), (mut b, len_b): (u64, u64)) -> Option<(String, String)> {
+        for _ in len_b..len_a {
+            a = self.step(a);
+        }
+        for _ in len_a..len_b {
+            b = self.step(b);
+        }
+        // If one walk started on the other one, they never merge from distinct values.
+        while a != b {
+            let (next_a, next_b) = (self.step(a), self.step(b));
+            if next_a == next_b {
+                let (first, second) = (self.string(a), self.string(b));
+                return if first != second { Some((first, second)) } else { None };
+            }
+            a = next_a;
+            b = next_b;
+        }
+        None
+    }
+
+    fn walk(&self, shared: &SharedWalks, seed: u64, found: Sender<(String, String)>) {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        while !shared.stop.load(Ordering::Relaxed) {
+            let start = self.start(&mut rng);
+            let (mut x, mut len) = (start, 0);
+            while len < self.max_steps && !shared.stop.load(Ordering::Relaxed) {
+                x = self.step(x);
+                len += 1;
+                if len % CHECK_INTERVAL == 0 {
+                    shared.steps.fetch_add(CHECK_INTERVAL, Ordering::Relaxed);
+                }
+                if self.is_distinguished(x) {
+                    break;
+                }
+            }
+            shared.steps.fetch_add(len % CHECK_INTERVAL, Ordering::Relaxed);
+            if !self.is_distinguished(x) {
+                continue;
+            }
+            let other = shared.points.lock().unwrap().insert(x, (start, len));
+            if let Some(coll) = other.and_then(|other| self.merge((start, len), other)) {
+                shared.stop.store(true, Ordering::Relaxed);
+                let _ = found.send(coll);
+                return;
+            }
+        }
+    }
+}
+
+fn find_single_walk(
+    hash: &PolynomialHash, alphabet: &[String], threads: usize, ctx: &mut Context,
+) -> Result<SingleCollision, AttackError> {
+    if hash.values() < MIN_WALK_VALUES {
+        return find_single_collision(hash, alphabet, ctx);
+    }
+    let walker = Walker::new(hash, alphabet, ctx.rng.gen());
+    let values = hash.values();
+    let points = 4 * ((values as f64).sqrt() as usize >> walker.distinguished_bits) + threads;
+    ctx.check_memory(points.saturating_mul(size_of::<(u64, (u64, u64))>() + 1))?;
+    let shared = SharedWalks::default();
+    let seeds: Vec<u64> = (0..threads).map(|_| ctx.rng.gen()).collect();
+    // Many times the expected number of steps, about sqrt(values) plus a walk per thread.
+    let max_steps = 64 * ((values as f64).sqrt() as u64 + ((threads as u64) << walker.distinguished_bits));
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        let workers: Vec<_> = seeds
+            .into_iter()
+            .map(|seed| {
+                let (walker, shared, sender) = (&walker, &shared, sender.clone());
+                scope.spawn(move || walker.walk(shared, seed, sender))
+            })
+            .collect();
+        drop(sender);
+        let result = loop {
+            match receiver.recv_timeout(REPORT_PERIOD) {
+                Ok((first, second)) => {
+                    break Ok(SingleCollision {
+                        first,
+                        second,
+                        length: walker.length,
+                        samples: shared.steps.load(Ordering::Relaxed),
+                    })
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    if let Err(e) = ctx.check_time() {
+                        break Err(e);
+                    }
+                    let samples = shared.steps.load(Ordering::Relaxed);
+                    if samples > max_steps {
+                        break Err(AttackError::LengthLimitExceeded {
+                            attack: "birthday",
+                            max_length: walker.length,
+                        });
+                    }
+                    ctx.report(Progress::Birthday {
+                        length: walker.length,
+                        samples,
+                    });
+                }
+                Err(RecvTimeoutError::Disconnected) => break Err(AttackError::WorkerFailed),
+            }
+        };
+        shared.stop.store(true, Ordering::Relaxed);
+        let failed = workers.into_iter().any(|w| w.join().is_err());
+        if failed {
+            Err(AttackError::WorkerFailed)
         } else {
-            return None;
+            result
         }
+    })
+}
+
+fn chain_collisions<F>(
+    hashes: &[PolynomialHash], init_alphabet: Vec<String>, ctx: &mut Context, mut find_single: F,
+) -> Result<Collision, AttackError>
+where
+    F: FnMut(&PolynomialHash, &[String], &mut Context) -> Result<SingleCollision, AttackError>,
+{
+    let start = Instant::now();
+    check_alphabet(&init_alphabet)?;
+    check_arithmetic(hashes)?;
+    let mut alphabet = init_alphabet;
+    let (mut depths, mut steps) = (Vec::with_capacity(hashes.len()), 0);
+    for hash in hashes {
+        let coll = find_single(hash, &alphabet, ctx)?;
+        depths.push(coll.length);
+        steps += coll.samples;
+        alphabet = vec![coll.first, coll.second];
     }
     let se = alphabet.remove(1);
     let fi = alphabet.remove(0);
-    Some((fi, se))
+    Ok(Collision {
+        hashes: hashes.iter().map(|h| h.evaluate(&fi)).collect(),
+        attack: "birthday",
+        length: fi.chars().count(),
+        first: fi,
+        second: se,
+        depths,
+        steps,
+        elapsed: start.elapsed(),
+    })
+}
+
+pub fn find_collision(
+    hashes: &[PolynomialHash], init_alphabet: Vec<String>, ctx: &mut Context,
+) -> Result<Collision, AttackError> {
+    chain_collisions(hashes, init_alphabet, ctx, find_single_collision)
+}
+
+/// Same as [`find_collision`], with `threads` parallel walks towards distinguished points instead
+/// of a table of samples.
+pub fn find_collision_distinguished(
+    hashes: &[PolynomialHash], init_alphabet: Vec<String>, threads: usize, ctx: &mut Context,
+) -> Result<Collision, AttackError> {
+    if threads == 0 {
+        return Err(AttackError::InvalidParameter("at least one thread is needed".to_string()));
+    }
+    chain_collisions(hashes, init_alphabet, ctx, |hash, alphabet, ctx| {
+        find_single_walk(hash, alphabet, threads, ctx)
+    })
 }
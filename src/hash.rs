@@ -0,0 +1,291 @@
+use crate::attack::{AttackError, Collision};
+use std::collections::BTreeMap;
+
+/// How characters are converted to numbers before being hashed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum CharMap {
+    /// Code point of the character, as in `h = h * B + s[i]`.
+    #[default]
+    Ascii,
+    /// Distance from `'a'` plus a shift, as in `h = h * B + (s[i] - 'a' + shift)`.
+    Offset(i64),
+    /// Explicit value of each character, the missing ones keep their code point.
+    Table(BTreeMap<char, i64>),
+}
+
+impl CharMap {
+    /// Value of a character, before reduction modulo the modulus.
+    pub fn value(&self, c: char) -> i128 {
+        match self {
+            CharMap::Ascii => c as i128,
+            CharMap::Offset(shift) => c as i128 - 'a' as i128 + *shift as i128,
+            CharMap::Table(table) => table.get(&c).map_or(c as i128, |&v| v as i128),
+        }
+    }
+}
+
+/// Order in which the powers of the base are assigned to the characters.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Direction {
+    /// `s[0] * B^(n-1) + ... + s[n-1]`, as computed by `h = h * B + s[i]`.
+    #[default]
+    LeftToRight,
+    /// `s[0] + s[1] * B + ... + s[n-1] * B^(n-1)`.
+    RightToLeft,
+}
+
+/// How the arithmetic operations of a hash are carried out.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Arithmetic {
+    /// Exact arithmetic modulo the modulus.
+    #[default]
+    Modular,
+    /// Two's complement `bits`-bit integers, which wrap on overflow, reduced with the C remainder
+    /// `%`, whose result has the sign of the dividend. The remainder is taken after every step if
+    /// `per_step`, only at the end otherwise, and never with a modulus of `0`.
+    Signed { bits: u32, per_step: bool },
+}
+
+/// Polynomial rolling hash.
+///
+/// A modulus of `0` stands for `2^64`, i.e. the natural overflow of `u64`, or for no remainder at
+/// all with signed arithmetic.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PolynomialHash {
+    pub base: u64,
+    pub modulus: u64,
+    pub char_map: CharMap,
+    /// Starting value of the hash, it is multiplied by `B^n` when hashing from the left and added
+    /// when hashing from the right.
+    pub initial: u64,
+    pub direction: Direction,
+    /// Whether the number of characters is added to the hash at the end.
+    pub length_term: bool,
+    pub arithmetic: Arithmetic,
+}
+
+impl PolynomialHash {
+    /// Returns the hash `h = h * base + s[i]` modulo `modulus`.
+    pub fn new(base: u64, modulus: u64) -> PolynomialHash {
+        PolynomialHash {
+            base,
+            modulus,
+            char_map: CharMap::default(),
+            initial: 0,
+            direction: Direction::default(),
+            length_term: false,
+            arithmetic: Arithmetic::default(),
+        }
+    }
+
+    fn reduce(&self, x: u128) -> u64 {
+        if self.modulus == 0 {
+            x as u64
+        } else {
+            (x % self.modulus as u128) as u64
+        }
+    }
+
+    /// Returns `a + b` modulo `modulus`.
+    pub fn add(&self, a: u64, b: u64) -> u64 {
+        self.reduce(a as u128 + b as u128)
+    }
+
+    /// Returns `a - b` modulo `modulus`, with `a` and `b` already reduced.
+    pub fn sub(&self, a: u64, b: u64) -> u64 {
+        if self.modulus == 0 {
+            a.wrapping_sub(b)
+        } else {
+            self.reduce(a as u128 + self.modulus as u128 - b as u128)
+        }
+    }
+
+    /// Returns `a * b` modulo `modulus`.
+    pub fn mul(&self, a: u64, b: u64) -> u64 {
+        self.reduce(a as u128 * b as u128)
+    }
+
+    /// Returns `base^exp` modulo `modulus`.
+    pub fn pow(&self, mut exp: usize) -> u64 {
+        let (mut res, mut b) = (self.reduce(1), self.reduce(self.base as u128));
+        while exp > 0 {
+            if exp & 1 == 1 {
+                res = self.mul(res, b);
+            }
+            b = self.mul(b, b);
+            exp >>= 1;
+        }
+        res
+    }
+
+    /// Value of a character modulo `modulus`.
+    pub fn char_value(&self, c: char) -> u64 {
+        let value = self.char_map.value(c);
+        if self.modulus == 0 {
+            value as u64
+        } else {
+            value.rem_euclid(self.modulus as i128) as u64
+        }
+    }
+
+    fn horner<I: Iterator<Item = u64>>(&self, init: u64, values: I) -> u64 {
+        values.fold(init, |h, v| self.add(self.mul(h, self.base), v))
+    }
+
+    /// Computes the hash of `s`. Negative results of signed arithmetic are returned in two's
+    /// complement.
+    ///
+    /// Panics if signed arithmetic doesn't have between 1 and 64 bits.
+    pub fn evaluate(&self, s: &str) -> u64 {
+        if let Arithmetic::Signed { bits, per_step } = self.arithmetic {
+            assert!((1..=64).contains(&bits), "signed arithmetic needs between 1 and 64 bits, got {}", bits);
+            return self.evaluate_signed(s, bits, per_step);
+        }
+        let initial = self.reduce(self.initial as u128);
+        let h = match self.direction {
+            Direction::LeftToRight => self.horner(initial, s.chars().map(|c| self.char_value(c))),
+            Direction::RightToLeft => {
+                let h = self.horner(0, s.chars().rev().map(|c| self.char_value(c)));
+                self.add(h, initial)
+            }
+        };
+        if self.length_term {
+            self.add(h, self.reduce(s.chars().count() as u128))
+        } else {
+            h
+        }
+    }
+
+    fn evaluate_signed(&self, s: &str, bits: u32, per_step: bool) -> u64 {
+        let shift = 128 - bits;
+        let wrap = |x: i128| (x << shift) >> shift;
+        let modulus = self.modulus as i128;
+        let rem = |x: i128, last: bool| if modulus != 0 && (per_step || last) { x % modulus } else { x };
+        let step = |h: i128, v: i128| rem(wrap(h.wrapping_mul(self.base as i128).wrapping_add(v)), false);
+        let initial = wrap(self.initial as i128);
+        let mut h = match self.direction {
+            Direction::LeftToRight => s.chars().fold(initial, |h, c| step(h, self.char_map.value(c))),
+            Direction::RightToLeft => {
+                let h = s.chars().rev().fold(0, |h, c| step(h, self.char_map.value(c)));
+                rem(wrap(h + initial), false)
+            }
+        };
+        if self.length_term {
+            h = rem(wrap(h + s.chars().count() as i128), false);
+        }
+        rem(h, true) as i64 as u64
+    }
+
+    /// Number of values the hash can take, at most `2^64`.
+    pub fn values(&self) -> u128 {
+        match self.arithmetic {
+            Arithmetic::Modular if self.modulus == 0 => 1 << 64,
+            Arithmetic::Modular => self.modulus as u128,
+            Arithmetic::Signed { bits, .. } if self.modulus == 0 => 1 << bits,
+            Arithmetic::Signed { bits, .. } => (2 * self.modulus as u128 - 1).min(1 << bits),
+        }
+    }
+
+    /// Returns a hash with modular arithmetic such that strings of the same length colliding under
+    /// it also collide under this hash, or `None` if the signed arithmetic can't be linearized.
+    ///
+    /// Wrapping is arithmetic modulo `2^bits`, which is enough when the remainder is only taken at
+    /// the end. With a remainder at every step, the hash is exact modular arithmetic as long as no
+    /// intermediate value overflows or becomes negative, which holds for characters with values in
+    /// `[0, modulus)`; the attacks check their collisions anyway. Signed arithmetic must have
+    /// between 1 and 64 bits.
+    pub fn linearize(&self) -> Option<PolynomialHash> {
+        let (bits, per_step) = match self.arithmetic {
+            Arithmetic::Modular => return Some(self.clone()),
+            Arithmetic::Signed { bits, per_step } if (1..=64).contains(&bits) => (bits, per_step),
+            Arithmetic::Signed { .. } => return None,
+        };
+        let modulus = if !per_step || self.modulus == 0 {
+            if bits == 64 {
+                0
+            } else {
+                1 << bits
+            }
+        } else if (self.modulus as u128 - 1) * (self.base as u128 + 1) < 1 << (bits - 1) {
+            self.modulus
+        } else {
+            return None;
+        };
+        Some(PolynomialHash {
+            modulus,
+            arithmetic: Arithmetic::Modular,
+            ..self.clone()
+        })
+    }
+
+    /// Computes the hash of `s1` minus the hash of `s2`, for strings with the same number of
+    /// characters. The initial value and the length term cancel out, so they are not taken into
+    /// account: this is why every attack builds strings of the same length.
+    pub fn difference(&self, s1: &str, s2: &str) -> u64 {
+        let diffs = s1.chars().zip(s2.chars()).map(|(c1, c2)| self.sub(self.char_value(c1), self.char_value(c2)));
+        match self.direction {
+            Direction::LeftToRight => self.horner(0, diffs),
+            Direction::RightToLeft => self.horner(0, diffs.collect::<Vec<_>>().into_iter().rev()),
+        }
+    }
+}
+
+/// Description of the hash under attack, made of one or more polynomial hashes that must all
+/// collide.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HashSpec {
+    pub hashes: Vec<PolynomialHash>,
+}
+
+impl HashSpec {
+    pub fn new(hashes: Vec<PolynomialHash>) -> HashSpec {
+        HashSpec { hashes }
+    }
+
+    /// Computes every hash of `s`.
+    pub fn evaluate(&self, s: &str) -> Vec<u64> {
+        self.hashes.iter().map(|h| h.evaluate(s)).collect()
+    }
+}
+
+/// Checks that the signed arithmetic of every hash has between 1 and 64 bits.
+pub(crate) fn check_arithmetic(hashes: &[PolynomialHash]) -> Result<(), AttackError> {
+    for hash in hashes {
+        if let Arithmetic::Signed { bits, .. } = hash.arithmetic {
+            if !(1..=64).contains(&bits) {
+                return Err(AttackError::InvalidHash(format!("signed arithmetic of {} bits", bits)));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Linearizes every hash with [`PolynomialHash::linearize`], for the attacks relying on modular
+/// arithmetic.
+pub(crate) fn linearize_all(hashes: &[PolynomialHash]) -> Result<Vec<PolynomialHash>, AttackError> {
+    check_arithmetic(hashes)?;
+    hashes
+        .iter()
+        .map(|h| {
+            h.linearize().ok_or_else(|| {
+                AttackError::InvalidHash(format!("signed arithmetic of {} {} overflows", h.base, h.modulus))
+            })
+        })
+        .collect()
+}
+
+/// Error of a collision of the linearized hashes which doesn't hold for the original ones.
+pub(crate) fn broken_signed_collision() -> AttackError {
+    let reason = "the collision doesn't hold with signed arithmetic, characters must be in [0, modulus)";
+    AttackError::InvalidHash(reason.to_string())
+}
+
+/// Recomputes the hashes of a collision found for the linearized `hashes`, checking that it still
+/// holds for the original ones.
+pub(crate) fn recheck(hashes: &[PolynomialHash], mut coll: Collision) -> Result<Collision, AttackError> {
+    coll.hashes = hashes.iter().map(|h| h.evaluate(&coll.first)).collect();
+    if hashes.iter().zip(&coll.hashes).any(|(h, &v)| h.evaluate(&coll.second) != v) {
+        return Err(broken_signed_collision());
+    }
+    Ok(coll)
+}
@@ -0,0 +1,356 @@
+use crate::attack::{check_alphabet, AttackError, Context};
+use crate::birthday_attack;
+use crate::hash::{broken_signed_collision, linearize_all, PolynomialHash};
+use std::collections::BTreeMap;
+use std::mem::size_of;
+use std::str::FromStr;
+
+/// Bucket counts taken by a libstdc++ `std::unordered_map` or `std::unordered_set` growing from
+/// its default size, as chosen by `_Prime_rehash_policy` with the default maximum load factor.
+pub const LIBSTDCXX_BUCKET_COUNTS: &[u64] = &[
+    1, 13, 29, 59, 127, 257, 541, 1109, 2357, 5087, 10273, 20753, 42043, 85229, 172933, 351061, 712697, 1447153,
+    2938679, 5967347, 12117689, 24607243, 49969847, 101473717, 206062531, 418451333, 849749479, 1725587117,
+    3504151727,
+];
+
+/// Sizes taken by a pb_ds `gp_hash_table` or `cc_hash_table` using `hash_prime_size_policy`.
+pub const PBDS_PRIME_SIZES: &[u64] = &[
+    5, 11, 23, 47, 97, 199, 409, 823, 1741, 3469, 6949, 14033, 28411, 57557, 116731, 236897, 480881, 976369, 1982627,
+    4026031, 8175383, 16601593, 33712729, 68460391, 139022417, 282312799, 573292817, 1164186217, 2364114217,
+    4294967291,
+];
+
+/// Initial size of a pb_ds table using the default `hash_exponential_size_policy`.
+const PBDS_MASK_START: u64 = 8;
+
+/// Integer keys colliding in a hash table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeySet {
+    pub keys: Vec<i64>,
+    /// Bucket counts at which all the keys fall in the same bucket.
+    pub bucket_counts: Vec<u64>,
+}
+
+/// Distinct strings with the same hashes, flooding a single bucket of a hash table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StringSet {
+    pub strings: Vec<String>,
+    /// Blocks of the same length and hashes, concatenated to build the strings.
+    pub blocks: Vec<String>,
+}
+
+/// How a pb_ds table maps hashes to buckets.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SizePolicy {
+    /// Default `hash_exponential_size_policy` with `direct_mask_range_hashing`: power of two sizes,
+    /// the bucket is given by the lowest bits of the hash.
+    #[default]
+    Mask,
+    /// `hash_prime_size_policy` with `direct_mod_range_hashing`: prime sizes, the bucket is the
+    /// hash modulo the size.
+    Prime,
+}
+
+/// Hash function applied to the keys, the custom ones commonly used to dodge anti-hash tests.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeyHash {
+    /// `std::hash`, i.e. the key converted to `size_t`.
+    #[default]
+    Identity,
+    /// `x ^ C`.
+    Xor(u64),
+    /// `x + C`, wrapping.
+    Add(u64),
+}
+
+/// Parses `identity`, `xor:C` or `add:C`, where `C` is decimal or hexadecimal with a `0x` prefix.
+impl FromStr for KeyHash {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<KeyHash, String> {
+        let parse = |c: &str| {
+            let parsed = match c.strip_prefix("0x") {
+                Some(hex) => u64::from_str_radix(hex, 16),
+                None => c.parse(),
+            };
+            parsed.map_err(|e| format!("invalid constant {}: {}", c, e))
+        };
+        match s.split_once(':') {
+            None if s == "identity" => Ok(KeyHash::Identity),
+            Some(("xor", c)) => Ok(KeyHash::Xor(parse(c)?)),
+            Some(("add", c)) => Ok(KeyHash::Add(parse(c)?)),
+            _ => Err(format!("expected identity, xor:C or add:C, got {}", s)),
+        }
+    }
+}
+
+impl KeyHash {
+    fn invert(self, y: u64) -> u64 {
+        match self {
+            KeyHash::Identity => y,
+            KeyHash::Xor(c) => y ^ c,
+            KeyHash::Add(c) => y.wrapping_sub(c),
+        }
+    }
+
+    /// Splits the hashes of the keys `[a, b]` (converted to `size_t`) into intervals.
+    fn image(self, a: u64, b: u64) -> Vec<(u64, u64)> {
+        match self {
+            KeyHash::Identity => vec![(a, b)],
+            KeyHash::Add(c) => {
+                let (a, b) = (a.wrapping_add(c), b.wrapping_add(c));
+                if a <= b {
+                    vec![(a, b)]
+                } else {
+                    vec![(a, u64::MAX), (0, b)]
+                }
+            }
+            // Xor maps an aligned block of 2^j keys to another aligned block.
+            KeyHash::Xor(c) => {
+                let (mut x, end) = (a as u128, b as u128 + 1);
+                let mut blocks = Vec::new();
+                while x < end {
+                    let mut j = x.trailing_zeros().min(64);
+                    while x + (1 << j) > end {
+                        j -= 1;
+                    }
+                    let low = ((1u128 << j) - 1) as u64;
+                    let start = (x as u64 ^ c) & !low;
+                    blocks.push((start, start | low));
+                    x += 1 << j;
+                }
+                blocks
+            }
+        }
+    }
+}
+
+/// Hashes of the keys in `[min, max]`, as intervals.
+fn hash_intervals(min: i64, max: i64, hash: KeyHash) -> Vec<(u64, u64)> {
+    // Negative keys are converted to size_t by adding 2^64.
+    let ranges = if min < 0 && max >= 0 {
+        vec![(min as u64, u64::MAX), (0, max as u64)]
+    } else {
+        vec![(min as u64, max as u64)]
+    };
+    ranges.into_iter().flat_map(|(a, b)| hash.image(a, b)).collect()
+}
+
+fn first_multiple(step: u128, c: u64) -> u128 {
+    (c as u128).div_ceil(step) * step
+}
+
+fn count_multiples(step: u128, intervals: &[(u64, u64)]) -> u128 {
+    let count = |&(c, d): &(u64, u64)| match first_multiple(step, c) {
+        first if first > d as u128 => 0,
+        first => (d as u128 - first) / step + 1,
+    };
+    intervals.iter().map(count).sum()
+}
+
+/// Returns `n` keys whose hash is a multiple of `step`, in increasing order.
+fn colliding_keys(step: u128, intervals: &[(u64, u64)], hash: KeyHash, n: usize) -> Vec<i64> {
+    let mut keys = Vec::with_capacity(n);
+    for &(c, d) in intervals {
+        let mut y = first_multiple(step, c);
+        while y <= d as u128 && keys.len() < n {
+            keys.push(hash.invert(y as u64) as i64);
+            y += step;
+        }
+    }
+    keys.sort_unstable();
+    keys
+}
+
+fn check_range(n: usize, min: i64, max: i64) -> Result<(), AttackError> {
+    if n == 0 || min > max {
+        return Err(AttackError::InvalidParameter("expected a non-empty range and at least one key".to_string()));
+    }
+    Ok(())
+}
+
+fn too_few_keys(n: usize, bucket_count: u64) -> AttackError {
+    let reason = format!("the range holds fewer than {} keys in the same bucket of {}", n, bucket_count);
+    AttackError::InvalidParameter(reason)
+}
+
+/// Keeps the largest suffix of `sizes` whose product still leaves `n` keys with a hash multiple
+/// of it, so that the table also degenerates while it grows.
+fn collide_suffix(sizes: &[u64], n: usize, min: i64, max: i64, hash: KeyHash) -> Result<KeySet, AttackError> {
+    check_range(n, min, max)?;
+    let intervals = hash_intervals(min, max, hash);
+    let (mut step, mut bucket_counts) = (1u128, Vec::new());
+    for &b in sizes.iter().rev() {
+        let next = step * b as u128;
+        if next > 1 << 64 || count_multiples(next, &intervals) < n as u128 {
+            break;
+        }
+        step = next;
+        bucket_counts.push(b);
+    }
+    if bucket_counts.is_empty() {
+        return Err(too_few_keys(n, sizes[sizes.len() - 1]));
+    }
+    bucket_counts.reverse();
+    Ok(KeySet {
+        keys: colliding_keys(step, &intervals, hash, n),
+        bucket_counts,
+    })
+}
+
+/// Returns the bucket count of a libstdc++ table after inserting `n` distinct keys, since the
+/// table grows on the first insertion, then as soon as it holds more elements than buckets.
+pub fn libstdcxx_bucket_count(n: usize) -> Option<u64> {
+    if n == 0 {
+        return Some(LIBSTDCXX_BUCKET_COUNTS[0]);
+    }
+    LIBSTDCXX_BUCKET_COUNTS[1..].iter().copied().find(|&b| b >= n as u64)
+}
+
+/// Returns `n` distinct keys in `[min, max]` which all fall in the same bucket of a libstdc++
+/// `std::unordered_map<long long, T>` holding them, whose hash is the identity.
+///
+/// The keys are multiples of the final bucket count, and of as many of the previous bucket counts
+/// as the range allows, so that the table also degenerates while it grows.
+pub fn libstdcxx_keys(n: usize, min: i64, max: i64) -> Result<KeySet, AttackError> {
+    let last = match LIBSTDCXX_BUCKET_COUNTS.iter().position(|&b| b > 1 && b >= n as u64) {
+        Some(last) => last,
+        None => return Err(AttackError::InvalidParameter("too many keys".to_string())),
+    };
+    collide_suffix(&LIBSTDCXX_BUCKET_COUNTS[1..=last], n, min, max, KeyHash::Identity)
+}
+
+/// Returns the size of a pb_ds table after inserting `n` distinct keys, since the table grows as
+/// soon as it is half full.
+pub fn pbds_size(n: usize, policy: SizePolicy) -> Option<u64> {
+    match policy {
+        SizePolicy::Mask => {
+            let mut size = PBDS_MASK_START;
+            while size / 2 <= n as u64 {
+                size = size.checked_mul(2)?;
+            }
+            Some(size)
+        }
+        SizePolicy::Prime => PBDS_PRIME_SIZES.iter().copied().find(|&s| s / 2 > n as u64),
+    }
+}
+
+/// Returns `n` distinct keys in `[min, max]` which all fall in the same bucket of a pb_ds
+/// `gp_hash_table<long long, T>` or `cc_hash_table<long long, T>` holding them.
+///
+/// With power of two sizes the hashes of the keys are multiples of the largest power of two the
+/// range allows, which all the sizes divide. With prime sizes they are multiples of the final size
+/// and of as many of the previous ones as possible.
+pub fn pbds_keys(n: usize, min: i64, max: i64, policy: SizePolicy, hash: KeyHash) -> Result<KeySet, AttackError> {
+    let last = match pbds_size(n, policy) {
+        Some(last) => last,
+        None => return Err(AttackError::InvalidParameter("too many keys".to_string())),
+    };
+    match policy {
+        SizePolicy::Mask => {
+            check_range(n, min, max)?;
+            let intervals = hash_intervals(min, max, hash);
+            let bits = (last.trailing_zeros()..64).rev().find(|&b| count_multiples(1 << b, &intervals) >= n as u128);
+            let bits = bits.ok_or_else(|| too_few_keys(n, last))?;
+            Ok(KeySet {
+                keys: colliding_keys(1 << bits, &intervals, hash, n),
+                bucket_counts: (PBDS_MASK_START.trailing_zeros()..=last.trailing_zeros()).map(|b| 1 << b).collect(),
+            })
+        }
+        SizePolicy::Prime => {
+            let last = PBDS_PRIME_SIZES.iter().position(|&s| s == last).unwrap();
+            collide_suffix(&PBDS_PRIME_SIZES[..=last], n, min, max, hash)
+        }
+    }
+}
+
+/// Largest number of blocks hashed when looking for a group of colliding blocks.
+const MAX_BLOCKS: usize = 1 << 18;
+
+/// Returns the largest group of strings of `length` words with the same hashes, the one with the
+/// largest hashes among the largest ones.
+fn block_group(hashes: &[PolynomialHash], length: usize, alphabet: &[String]) -> Vec<String> {
+    let mut groups: BTreeMap<Vec<u64>, Vec<String>> = BTreeMap::new();
+    let mut digits = vec![0; length];
+    loop {
+        let block: String = digits.iter().map(|&d| alphabet[d].as_str()).collect();
+        groups.entry(hashes.iter().map(|h| h.evaluate(&block)).collect()).or_default().push(block);
+        match digits.iter().position(|&d| d + 1 < alphabet.len()) {
+            Some(i) => {
+                digits[i] += 1;
+                digits[..i].iter_mut().for_each(|d| *d = 0);
+            }
+            None => break,
+        }
+    }
+    groups.into_values().max_by_key(|g| g.len()).unwrap_or_default()
+}
+
+/// Number of blocks from a group of `size` needed to build `count` distinct strings.
+fn blocks_needed(size: usize, count: usize) -> usize {
+    let (mut blocks, mut strings) = (1, size as u128);
+    while strings < count as u128 {
+        strings *= size as u128;
+        blocks += 1;
+    }
+    blocks
+}
+
+/// Returns `count` distinct strings of at most `max_length` characters with the same value under
+/// every hash, e.g. `String.hashCode` for a Java `HashMap<String, T>`.
+///
+/// Any concatenation of blocks of the same length and hashes has the same hashes, so the strings
+/// are built from the group of blocks giving the shortest strings, found among all the short
+/// blocks or as a collision of the birthday attack.
+pub fn equal_hash_strings(
+    hashes: &[PolynomialHash], count: usize, max_length: usize, alphabet: &[String], ctx: &mut Context,
+) -> Result<StringSet, AttackError> {
+    check_alphabet(alphabet)?;
+    if count == 0 || hashes.is_empty() {
+        return Err(AttackError::InvalidParameter("expected at least one hash and one string".to_string()));
+    }
+    // Blocks are compared with wrapping arithmetic, whose collisions survive concatenation.
+    let linear = linearize_all(hashes)?;
+    let word_len = alphabet[0].chars().count();
+    let length_of = |group: &[String]| blocks_needed(group.len(), count) * group[0].chars().count();
+    let (mut best, mut blocks) = (None, alphabet.len());
+    for length in 1.. {
+        if blocks > MAX_BLOCKS {
+            break;
+        }
+        ctx.check_memory(blocks * (size_of::<(Vec<u64>, String)>() + length * word_len))?;
+        ctx.check_time()?;
+        let group = block_group(&linear, length, alphabet);
+        if group.len() > 1 && best.as_ref().is_none_or(|b: &Vec<String>| length_of(&group) < length_of(b)) {
+            best = Some(group);
+        }
+        blocks = blocks.saturating_mul(alphabet.len());
+    }
+    let group = match best {
+        Some(group) => group,
+        None => {
+            let coll = birthday_attack::find_collision(&linear, alphabet.to_vec(), ctx)?;
+            vec![coll.first, coll.second]
+        }
+    };
+    let length = length_of(&group);
+    if length > max_length {
+        return Err(AttackError::LengthTooShort { min_length: length });
+    }
+    ctx.check_memory(count.saturating_mul(size_of::<String>() + length))?;
+    let strings: Vec<String> = (0..count)
+        .map(|mut i| {
+            let mut s = String::with_capacity(length);
+            for _ in 0..blocks_needed(group.len(), count) {
+                s.push_str(&group[i % group.len()]);
+                i /= group.len();
+            }
+            s
+        })
+        .collect();
+    let values: Vec<u64> = hashes.iter().map(|h| h.evaluate(&strings[0])).collect();
+    if strings.iter().any(|s| hashes.iter().zip(&values).any(|(h, &v)| h.evaluate(s) != v)) {
+        return Err(broken_signed_collision());
+    }
+    Ok(StringSet { strings, blocks: group })
+}
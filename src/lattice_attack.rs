@@ -0,0 +1,229 @@
+use crate::attack::{check_alphabet, Attack, AttackError, Collision, Context, Progress};
+use crate::hash::{linearize_all, Direction, HashSpec, PolynomialHash};
+use rand::Rng;
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::time::Instant;
+
+/// Lattice reduction attack, looking for a single pair of strings colliding under all the hashes
+/// at once.
+#[derive(Clone, Debug)]
+pub struct LatticeAttack {
+    /// Characters used to build the strings, every word must have a single character.
+    pub alphabet: Vec<String>,
+}
+
+impl Default for LatticeAttack {
+    fn default() -> LatticeAttack {
+        LatticeAttack {
+            alphabet: crate::default_alphabet(),
+        }
+    }
+}
+
+impl Attack for LatticeAttack {
+    fn name(&self) -> &'static str {
+        "lattice"
+    }
+
+    fn run(&self, target: &HashSpec, ctx: &mut Context) -> Result<Collision, AttackError> {
+        find_collision(&target.hashes, &self.alphabet, ctx)
+    }
+}
+
+const MAX_LENGTH: usize = 128;
+const CHECK_INTERVAL: u64 = 1 << 10;
+/// Lovász condition parameter of the reduction.
+const DELTA: f64 = 0.99;
+/// Size reductions by larger multiples lose too much precision, the projections are recomputed.
+const MAX_EXACT_MULTIPLE: f64 = (1u64 << 26) as f64;
+/// Largest entry of the initial basis, leaving room for the size reductions in `i128`.
+const MAX_ENTRY: i128 = 1 << 100;
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+/// LLL reduction of the rows of `basis`, with floating point Gram–Schmidt vectors recomputed from
+/// the exact basis as in the Schnorr–Euchner variant. Returns the number of swaps.
+fn reduce(basis: &mut [Vec<i128>], length: usize, ctx: &mut Context) -> Result<u64, AttackError> {
+    let (n, dim) = (basis.len(), basis[0].len());
+    let mut ortho = vec![vec![0.0; dim]; n];
+    let mut norms = vec![0.0; n];
+    let mut mu = vec![vec![0.0; n]; n];
+    let (mut k, mut swaps) = (0, 0);
+    while k < n {
+        loop {
+            let bk: Vec<f64> = basis[k].iter().map(|&x| x as f64).collect();
+            for j in 0..k {
+                mu[k][j] = dot(&bk, &ortho[j]) / norms[j];
+            }
+            let mut exact = true;
+            for j in (0..k).rev() {
+                let r = mu[k][j].round();
+                if r == 0.0 {
+                    continue;
+                }
+                let (head, tail) = basis.split_at_mut(k);
+                for (x, &y) in tail[0].iter_mut().zip(&head[j]) {
+                    *x -= r as i128 * y;
+                }
+                let (head, tail) = mu.split_at_mut(k);
+                for (x, y) in tail[0][..j].iter_mut().zip(&head[j][..j]) {
+                    *x -= r * y;
+                }
+                mu[k][j] -= r;
+                exact &= r.abs() <= MAX_EXACT_MULTIPLE;
+            }
+            if exact {
+                break;
+            }
+        }
+        let mut bk: Vec<f64> = basis[k].iter().map(|&x| x as f64).collect();
+        for j in 0..k {
+            mu[k][j] = dot(&bk, &ortho[j]) / norms[j];
+            for (x, y) in bk.iter_mut().zip(&ortho[j]) {
+                *x -= mu[k][j] * y;
+            }
+        }
+        norms[k] = dot(&bk, &bk);
+        ortho[k] = bk;
+        if k > 0 && norms[k] < (DELTA - mu[k][k - 1] * mu[k][k - 1]) * norms[k - 1] {
+            basis.swap(k, k - 1);
+            swaps += 1;
+            if swaps % CHECK_INTERVAL == 0 {
+                ctx.check_time()?;
+                ctx.report(Progress::Lattice { length, swaps });
+            }
+            k -= 1;
+        } else {
+            k += 1;
+        }
+    }
+    Ok(swaps)
+}
+
+/// Builds the lattice of the differences between two strings of `length` characters: a vector
+/// `x` whose hash columns are zero satisfies `sum x[i] * base^shift(i) = 0` modulo every modulus.
+/// The hash columns are multiplied by `weight` so that the reduction favours such vectors.
+fn build_lattice(hashes: &[PolynomialHash], length: usize, weight: i128) -> Vec<Vec<i128>> {
+    let dim = length + hashes.len();
+    let moduli: Vec<i128> = hashes.iter().map(|h| if h.modulus == 0 { 1 << 64 } else { h.modulus as i128 }).collect();
+    let mut basis = Vec::with_capacity(dim);
+    for i in 0..length {
+        let mut row = vec![0; dim];
+        row[i] = 1;
+        for (j, hash) in hashes.iter().enumerate() {
+            let shift = match hash.direction {
+                Direction::LeftToRight => length - 1 - i,
+                Direction::RightToLeft => i,
+            };
+            let mut c = hash.pow(shift) as i128 % moduli[j];
+            if 2 * c > moduli[j] {
+                c -= moduli[j];
+            }
+            row[length + j] = weight * c;
+        }
+        basis.push(row);
+    }
+    for (j, &modulus) in moduli.iter().enumerate() {
+        let mut row = vec![0; dim];
+        row[length + j] = weight * modulus;
+        basis.push(row);
+    }
+    basis
+}
+
+/// Turns the differences `diffs` between the characters of two strings into the strings.
+fn build_strings(
+    diffs: &[i128], pairs: &HashMap<i128, (char, char)>, alphabet: &[char], ctx: &mut Context,
+) -> Option<(String, String)> {
+    let (mut fi, mut se) = (String::new(), String::new());
+    for d in diffs {
+        let (c1, c2) = match d {
+            0 => {
+                let c = alphabet[ctx.rng.gen_range(0, alphabet.len())];
+                (c, c)
+            }
+            d => *pairs.get(d)?,
+        };
+        fi.push(c1);
+        se.push(c2);
+    }
+    Some((fi, se))
+}
+
+pub fn find_collision(
+    hashes: &[PolynomialHash], alphabet: &[String], ctx: &mut Context,
+) -> Result<Collision, AttackError> {
+    let start = Instant::now();
+    check_alphabet(alphabet)?;
+    if alphabet[0].chars().count() != 1 {
+        return Err(AttackError::InvalidParameter("the lattice attack needs single character words".to_string()));
+    }
+    if hashes.is_empty() {
+        return Err(AttackError::InvalidParameter("the lattice attack needs at least one hash".to_string()));
+    }
+    let linear = linearize_all(hashes)?;
+    let chars: Vec<char> = alphabet.iter().map(|w| w.chars().next().unwrap()).collect();
+    // Each coordinate of the lattice is the difference of two characters under every hash.
+    let mut pairs = HashMap::new();
+    for &c1 in &chars {
+        for &c2 in &chars {
+            let diff = |h: &PolynomialHash| h.char_map.value(c1) - h.char_map.value(c2);
+            let d = diff(&hashes[0]);
+            if hashes.iter().any(|h| diff(h) != d) {
+                let reason = "the lattice attack needs the same differences between characters in every hash";
+                return Err(AttackError::InvalidParameter(reason.to_string()));
+            }
+            if d != 0 {
+                pairs.insert(d, (c1, c2));
+            }
+        }
+    }
+    let max_diff = pairs.keys().max().copied().unwrap_or(1);
+    // Roughly (2 * max_diff + 1)^length differences must outnumber the values of the hashes.
+    let bits: f64 = linear.iter().map(|h| if h.modulus == 0 { 64.0 } else { (h.modulus as f64).log2() }).sum();
+    let mut length = ((bits / ((2 * max_diff + 1) as f64).log2()).ceil() as usize).max(2);
+    let max_modulus = linear.iter().map(|h| if h.modulus == 0 { 1 << 64 } else { h.modulus as i128 }).max().unwrap();
+    let mut steps = 0;
+    while length <= MAX_LENGTH {
+        let dim = length + hashes.len();
+        ctx.check_memory(dim * dim * (size_of::<i128>() + 2 * size_of::<f64>()))?;
+        let weight = (4 * length as i128).checked_mul(max_diff);
+        let largest = weight.and_then(|w| w.checked_mul(max_modulus)).filter(|&e| e <= MAX_ENTRY);
+        if largest.is_none() {
+            let reason = "the values of the characters are too large for the lattice attack";
+            return Err(AttackError::InvalidParameter(reason.to_string()));
+        }
+        let mut basis = build_lattice(&linear, length, weight.unwrap());
+        steps += reduce(&mut basis, length, ctx)?;
+        for row in &basis {
+            let (diffs, columns) = row.split_at(length);
+            if columns.iter().any(|&c| c != 0) || diffs.iter().all(|&d| d == 0) {
+                continue;
+            }
+            if let Some((fi, se)) = build_strings(diffs, &pairs, &chars, ctx) {
+                let values: Vec<u64> = hashes.iter().map(|h| h.evaluate(&fi)).collect();
+                if hashes.iter().zip(&values).all(|(h, &v)| h.evaluate(&se) == v) {
+                    return Ok(Collision {
+                        hashes: values,
+                        attack: "lattice",
+                        length,
+                        first: fi,
+                        second: se,
+                        depths: vec![length; hashes.len()],
+                        steps,
+                        elapsed: start.elapsed(),
+                    });
+                }
+            }
+        }
+        ctx.report(Progress::Lattice { length, swaps: steps });
+        length += length / 4 + 1;
+    }
+    Err(AttackError::LengthLimitExceeded {
+        attack: "lattice",
+        max_length: MAX_LENGTH,
+    })
+}
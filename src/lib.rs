@@ -0,0 +1,31 @@
+//! Antihash testcase builder.
+//!
+//! This crate provides four different ways to compute antihash-testcases:
+//! - [`overflow_attack`] for hashes computed modulo a power of two;
+//! - [`birthday_attack`] for small moduli, or up to `2^64` by walking to distinguished points;
+//! - [`tree_attack`] for large moduli;
+//! - [`lattice_attack`] for several large moduli at once.
+//!
+//! [`hash_table_attack`] builds integer keys colliding in the hash tables of standard libraries and of pb_ds,
+//! and many strings with the same hash, such as keys of a Java `HashMap<String, T>`.
+
+pub mod attack;
+pub mod birthday_attack;
+pub mod hash;
+pub mod hash_table_attack;
+pub mod lattice_attack;
+pub mod overflow_attack;
+pub mod preset;
+pub mod tree_attack;
+
+pub use attack::{Attack, AttackError, Collision, Context, Progress};
+pub use birthday_attack::BirthdayAttack;
+pub use hash::{Arithmetic, CharMap, Direction, HashSpec, PolynomialHash};
+pub use lattice_attack::LatticeAttack;
+pub use overflow_attack::OverflowAttack;
+pub use tree_attack::TreeAttack;
+
+/// Returns the default alphabet, made of the lowercase latin letters.
+pub fn default_alphabet() -> Vec<String> {
+    (b'a'..=b'z').map(|c| char::from(c).to_string()).collect()
+}
@@ -1,20 +1,27 @@
 extern crate clap;
 
-use clap::{App, AppSettings, Arg, SubCommand};
+use antihash::birthday_attack::Strategy;
+use antihash::hash_table_attack::{self, KeyHash, KeySet, SizePolicy};
+use antihash::{
+    default_alphabet, preset, Arithmetic, Attack, AttackError, BirthdayAttack, CharMap, Collision, Context, Direction,
+    HashSpec, LatticeAttack, OverflowAttack, PolynomialHash, Progress, TreeAttack,
+};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use std::cell::Cell;
+use std::collections::BTreeMap;
 use std::fmt::Display;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::process;
 use std::str::FromStr;
-
-mod birthday_attack;
-mod overflow_attack;
-mod tree_attack;
+use std::time::Duration;
 
 struct WordValidator {
     len: Cell<Option<usize>>,
 }
 
 impl WordValidator {
-    fn new() -> Box<dyn Fn(String) -> Result<(), String>> {
+    fn boxed() -> Box<dyn Fn(String) -> Result<(), String>> {
         let validator = WordValidator {
             len: Cell::new(None),
         };
@@ -49,8 +56,404 @@ where
     }
 }
 
-fn main() {
-    let matches = App::new("antihash")
+fn parse_char_map(s: &str) -> Result<CharMap, String> {
+    if s == "ascii" {
+        return Ok(CharMap::Ascii);
+    }
+    if let Some(shift) = s.strip_prefix("offset:") {
+        return shift.parse().map(CharMap::Offset).map_err(|e| format!("{}", e));
+    }
+    if let Some(path) = s.strip_prefix("table:") {
+        let content = fs::read_to_string(path).map_err(|e| format!("{}", e))?;
+        let mut table = BTreeMap::new();
+        for line in content.lines().filter(|l| !l.trim().is_empty()) {
+            let mut chars = line.chars();
+            let c = chars.next().unwrap();
+            let value = chars.as_str().trim().parse().map_err(|e| format!("{}: {}", line, e))?;
+            table.insert(c, value);
+        }
+        return Ok(CharMap::Table(table));
+    }
+    Err("expected ascii, offset:SHIFT or table:FILE".to_string())
+}
+
+/// Options describing the hashes, which override the ones of a preset when given explicitly.
+const HASH_OPTIONS: [&str; 5] = ["char-map", "direction", "initial", "length-term", "signed"];
+
+/// Properties shared by the hashes given on the command line.
+struct HashOptions {
+    char_map: CharMap,
+    directions: Vec<Direction>,
+    initials: Vec<u64>,
+    length_term: bool,
+    arithmetic: Arithmetic,
+    /// Hash options given explicitly.
+    given: Vec<&'static str>,
+}
+
+impl HashOptions {
+    fn from_matches(matches: &ArgMatches) -> HashOptions {
+        let directions = matches.values_of("direction").unwrap().map(|d| match d {
+            "left" => Direction::LeftToRight,
+            _ => Direction::RightToLeft,
+        });
+        HashOptions {
+            char_map: parse_char_map(matches.value_of("char-map").unwrap()).unwrap(),
+            directions: directions.collect(),
+            initials: matches.values_of("initial").unwrap().map(|i| i.parse().unwrap()).collect(),
+            length_term: matches.is_present("length-term"),
+            arithmetic: match matches.value_of("signed") {
+                Some(bits) => Arithmetic::Signed {
+                    bits: bits.parse().unwrap(),
+                    per_step: !matches.is_present("final-mod"),
+                },
+                None => Arithmetic::Modular,
+            },
+            given: HASH_OPTIONS.iter().copied().filter(|&o| matches.occurrences_of(o) > 0).collect(),
+        }
+    }
+
+    /// Builds the `index`-th hash, the last value of each list applies to all the remaining hashes.
+    fn build(&self, index: usize, base: u64, modulus: u64) -> PolynomialHash {
+        PolynomialHash {
+            char_map: self.char_map.clone(),
+            initial: self.initials[index.min(self.initials.len() - 1)],
+            direction: self.directions[index.min(self.directions.len() - 1)],
+            length_term: self.length_term,
+            arithmetic: self.arithmetic,
+            ..PolynomialHash::new(base, modulus)
+        }
+    }
+
+    /// Replaces the properties of the `index`-th hash of a preset with the options given explicitly.
+    fn customize(&self, index: usize, hash: &mut PolynomialHash) {
+        let built = self.build(index, hash.base, hash.modulus);
+        let given = |option| self.given.contains(&option);
+        if given("char-map") {
+            hash.char_map = built.char_map;
+        }
+        if given("direction") {
+            hash.direction = built.direction;
+        }
+        if given("initial") {
+            hash.initial = built.initial;
+        }
+        if given("length-term") {
+            hash.length_term = true;
+        }
+        if given("signed") {
+            hash.arithmetic = built.arithmetic;
+        }
+    }
+}
+
+/// Builds the target from the preset if given, customized by the hash options given explicitly,
+/// or from the coefficients otherwise.
+fn get_hash_spec(matches: &ArgMatches, options: &HashOptions) -> HashSpec {
+    if let Some(name) = matches.value_of("preset") {
+        let mut target = preset::find(name).unwrap().hash_spec();
+        for (i, hash) in target.hashes.iter_mut().enumerate() {
+            options.customize(i, hash);
+        }
+        return target;
+    }
+    let coefficients: Vec<u64> = matches.values_of("coefficients").unwrap().map(|c| c.parse().unwrap()).collect();
+    let hashes = coefficients.chunks(2).enumerate().map(|(i, c)| options.build(i, c[0], c[1]));
+    HashSpec::new(hashes.collect())
+}
+
+fn key_range_args<'a, 'b>() -> [Arg<'a, 'b>; 3] {
+    [
+        Arg::with_name("count")
+            .short("n")
+            .long("count")
+            .help("Number of distinct keys")
+            .required(true)
+            .takes_value(true)
+            .value_name("COUNT")
+            .validator(is_valid::<usize>),
+        Arg::with_name("min")
+            .long("min")
+            .help("Smallest allowed key")
+            .takes_value(true)
+            .value_name("MIN")
+            .default_value("0")
+            .allow_hyphen_values(true)
+            .validator(is_valid::<i64>),
+        Arg::with_name("max")
+            .long("max")
+            .help("Largest allowed key")
+            .takes_value(true)
+            .value_name("MAX")
+            .default_value("1000000000000000000")
+            .allow_hyphen_values(true)
+            .validator(is_valid::<i64>),
+    ]
+}
+
+fn preset_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("preset")
+        .short("p")
+        .long("preset")
+        .help("Attack a known hash instead of BASE MODULE pairs, see the presets subcommand")
+        .takes_value(true)
+        .value_name("NAME")
+        .conflicts_with("coefficients")
+        .validator(|s| match preset::find(&s) {
+            Some(_) => Ok(()),
+            None => Err("unknown preset".to_string()),
+        })
+}
+
+fn print_presets() {
+    for preset in preset::PRESETS {
+        println!("{}: {}", preset.name, preset.description);
+        for hash in preset.hash_spec().hashes {
+            let arithmetic = match hash.arithmetic {
+                Arithmetic::Signed { bits, per_step } if per_step || hash.modulus == 0 => {
+                    format!("signed {}-bit", bits)
+                }
+                Arithmetic::Signed { bits, .. } => format!("signed {}-bit, final remainder", bits),
+                Arithmetic::Modular => "modular".to_string(),
+            };
+            println!("    base {} modulus {} initial {} {}", hash.base, hash.modulus, hash.initial, arithmetic);
+        }
+    }
+}
+
+fn get_context(matches: &ArgMatches) -> Context {
+    let mut ctx = match matches.value_of("seed") {
+        Some(seed) => Context::new(seed.parse().unwrap()),
+        None => Context::default(),
+    };
+    if matches.is_present("verbose") {
+        ctx = ctx.with_observer(print_progress);
+    }
+    if let Some(timeout) = matches.value_of("timeout") {
+        ctx = ctx.with_timeout(Duration::from_secs(timeout.parse().unwrap()));
+    }
+    if let Some(max_memory) = matches.value_of("max-memory") {
+        ctx = ctx.with_max_memory(max_memory.parse::<usize>().unwrap().saturating_mul(1 << 20));
+    }
+    eprintln!("Seed: {}", ctx.seed());
+    ctx
+}
+
+fn get_alphabet(matches: &ArgMatches) -> Vec<String> {
+    match matches.values_of("alphabet") {
+        Some(a) => a.map(|s| s.to_string()).collect(),
+        None => default_alphabet(),
+    }
+}
+
+fn read_strings(matches: &ArgMatches) -> io::Result<(String, String)> {
+    let trim = |s: &str| s.trim_end_matches(['\n', '\r']).to_string();
+    if let Some(mut files) = matches.values_of("files") {
+        let fi = fs::read_to_string(files.next().unwrap())?;
+        let se = fs::read_to_string(files.next().unwrap())?;
+        return Ok((trim(&fi), trim(&se)));
+    }
+    let mut input = String::new();
+    io::stdin().read_to_string(&mut input)?;
+    first_lines(&input)
+}
+
+fn first_lines(input: &str) -> io::Result<(String, String)> {
+    let mut lines = input.lines();
+    match (lines.next(), lines.next()) {
+        (Some(fi), Some(se)) => Ok((fi.to_string(), se.to_string())),
+        _ => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "expected two lines")),
+    }
+}
+
+fn verify(matches: &ArgMatches, options: &HashOptions) -> i32 {
+    let target = get_hash_spec(matches, options);
+    let (fi, se) = match read_strings(matches) {
+        Ok(strings) => strings,
+        Err(e) => {
+            eprintln!("Unable to read strings: {}", e);
+            return 2;
+        }
+    };
+    let mut valid = true;
+    for hash in &target.hashes {
+        let (h1, h2) = (hash.evaluate(&fi), hash.evaluate(&se));
+        let status = if h1 == h2 { "OK" } else { "DIFFERENT" };
+        let show = |h: u64| match hash.arithmetic {
+            Arithmetic::Signed { .. } => (h as i64).to_string(),
+            Arithmetic::Modular => h.to_string(),
+        };
+        println!("{} {}: {} {} {}", hash.base, hash.modulus, show(h1), show(h2), status);
+        valid &= h1 == h2;
+    }
+    if fi == se {
+        println!("Strings are identical");
+        valid = false;
+    }
+    if valid {
+        0
+    } else {
+        1
+    }
+}
+
+fn key_range(matches: &ArgMatches) -> (usize, i64, i64) {
+    let count = matches.value_of("count").unwrap().parse().unwrap();
+    let min = matches.value_of("min").unwrap().parse().unwrap();
+    let max = matches.value_of("max").unwrap().parse().unwrap();
+    (count, min, max)
+}
+
+fn unordered_map(matches: &ArgMatches) -> i32 {
+    let (count, min, max) = key_range(matches);
+    print_key_set(hash_table_attack::libstdcxx_keys(count, min, max))
+}
+
+fn pbds(matches: &ArgMatches) -> i32 {
+    let (count, min, max) = key_range(matches);
+    let policy = match matches.value_of("policy").unwrap() {
+        "mask" => SizePolicy::Mask,
+        _ => SizePolicy::Prime,
+    };
+    let hash = matches.value_of("hash").unwrap().parse().unwrap();
+    print_key_set(hash_table_attack::pbds_keys(count, min, max, policy, hash))
+}
+
+fn hash_map(matches: &ArgMatches, submatches: &ArgMatches, options: &HashOptions) -> i32 {
+    let target = get_hash_spec(submatches, options);
+    let count = submatches.value_of("count").unwrap().parse().unwrap();
+    let max_length = submatches.value_of("max-length").unwrap().parse().unwrap();
+    let alphabet = get_alphabet(submatches);
+    let mut ctx = get_context(matches);
+    let mut set = match hash_table_attack::equal_hash_strings(&target.hashes, count, max_length, &alphabet, &mut ctx) {
+        Ok(set) => set,
+        Err(e) => {
+            eprintln!("Strings not found: {}", e);
+            return 1;
+        }
+    };
+    for s in &mut set.strings {
+        if matches.is_present("reverse") {
+            *s = s.chars().rev().collect();
+        }
+        if matches.is_present("uppercase") {
+            s.make_ascii_uppercase();
+        }
+    }
+    let values = target.evaluate(&set.strings[0]);
+    let distinct: std::collections::HashSet<_> = set.strings.iter().collect();
+    if distinct.len() != set.strings.len() || set.strings.iter().any(|s| target.evaluate(s) != values) {
+        eprintln!("Refusing to print strings that don't collide anymore after --reverse/--uppercase");
+        return 1;
+    }
+    eprintln!("Combining blocks: {}", set.blocks.join(" "));
+    print_lines(&set.strings);
+    0
+}
+
+fn print_key_set(result: Result<KeySet, AttackError>) -> i32 {
+    match result {
+        Ok(set) => {
+            let counts: Vec<_> = set.bucket_counts.iter().map(|b| b.to_string()).collect();
+            eprintln!("Colliding for bucket counts: {}", counts.join(" "));
+            print_lines(&set.keys);
+            0
+        }
+        Err(e) => {
+            eprintln!("Keys not found: {}", e);
+            1
+        }
+    }
+}
+
+fn print_lines<T: Display>(lines: &[T]) {
+    let stdout = io::stdout();
+    let mut out = io::BufWriter::new(stdout.lock());
+    for line in lines {
+        if writeln!(out, "{}", line).is_err() {
+            return;
+        }
+    }
+}
+
+fn print_progress(progress: &Progress) {
+    match progress {
+        Progress::Tree {
+            depth,
+            phase,
+            cluster,
+            clusters,
+            fill,
+            best,
+        } => {
+            let best = best.map_or("-".to_string(), |b| b.to_string());
+            eprintln!(
+                "tree: depth {} phase {} cluster {}/{} fill {} best {}",
+                depth,
+                phase,
+                cluster + 1,
+                clusters,
+                fill,
+                best
+            );
+        }
+        Progress::Birthday { length, samples } => eprintln!("birthday: length {} samples {}", length, samples),
+        Progress::Lattice { length, swaps } => eprintln!("lattice: length {} swaps {}", length, swaps),
+    }
+}
+
+/// Odd bases checked when the overflow attack targets every odd base: the worst case `3`, for
+/// which the Thue–Morse pair needs the most words, and common ones.
+const OVERFLOW_CHECK_BASES: [u64; 3] = [3, 31, 9973];
+
+/// Hashes checking a collision of the overflow attack without `--base`, modulo `2^bits` or `2^64`
+/// if larger.
+fn overflow_check(bits: u32, options: &HashOptions) -> HashSpec {
+    let modulus = if bits < 64 { 1u64 << bits } else { 0 };
+    HashSpec::new(OVERFLOW_CHECK_BASES.iter().enumerate().map(|(i, &b)| options.build(i, b, modulus)).collect())
+}
+
+/// Prints the strings of `coll` after --reverse and --uppercase, if they still collide under the
+/// hashes of `check`, and returns the exit code.
+fn print_collision(matches: &ArgMatches, target: &HashSpec, check: &HashSpec, mut coll: Collision) -> i32 {
+    if matches.is_present("reverse") {
+        coll.first = coll.first.chars().rev().collect::<String>();
+        coll.second = coll.second.chars().rev().collect::<String>();
+    }
+    if matches.is_present("uppercase") {
+        coll.first.make_ascii_uppercase();
+        coll.second.make_ascii_uppercase();
+    }
+    let broken = check.hashes.iter().filter(|h| h.evaluate(&coll.first) != h.evaluate(&coll.second));
+    let broken: Vec<String> = broken.map(|h| format!("{} {}", h.base, h.modulus)).collect();
+    if coll.first == coll.second || !broken.is_empty() {
+        eprintln!("Refusing to print strings that don't collide anymore after --reverse/--uppercase");
+        if !broken.is_empty() {
+            eprintln!("Broken hashes: {}", broken.join(", "));
+        }
+        return 1;
+    }
+    coll.hashes = target.evaluate(&coll.first);
+    if matches.is_present("stats") {
+        print_stats(&coll);
+    }
+
+    print_lines(&[coll.first, coll.second]);
+    0
+}
+
+fn print_stats(coll: &Collision) {
+    let join = |v: Vec<String>| v.join(" ");
+    eprintln!("attack: {}", coll.attack);
+    eprintln!("length: {}", coll.length);
+    eprintln!("hashes: {}", join(coll.hashes.iter().map(|h| h.to_string()).collect()));
+    eprintln!("depths: {}", join(coll.depths.iter().map(|d| d.to_string()).collect()));
+    eprintln!("steps: {}", coll.steps);
+    eprintln!("elapsed: {:.3}s", coll.elapsed.as_secs_f64());
+}
+
+fn app() -> App<'static, 'static> {
+    App::new("antihash")
         .version("1.0.0")
         .author("Alessandro Bortolin <bortolin.alessandro@outlook.it>")
         .about("Find antihash testcases")
@@ -61,7 +464,7 @@ fn main() {
             Arg::with_name("reverse")
                 .short("r")
                 .long("reverse")
-                .help("Print reversed strings")
+                .help("Print reversed strings, use --direction to attack hashes evaluated from the right")
                 .display_order(100),
         )
         .arg(
@@ -71,6 +474,103 @@ fn main() {
                 .help("Print uppercase strings")
                 .display_order(100),
         )
+        .arg(
+            Arg::with_name("char-map")
+                .long("char-map")
+                .help("Value of characters: ascii, offset:SHIFT (s[i] - 'a' + SHIFT) or table:FILE (lines \"C VALUE\")")
+                .takes_value(true)
+                .value_name("MAP")
+                .default_value("ascii")
+                .validator(|s| parse_char_map(&s).map(|_| ()))
+                .display_order(100),
+        )
+        .arg(
+            Arg::with_name("direction")
+                .long("direction")
+                .help("Direction of each hash: left (h = h * B + s[i]) or right (sum of s[i] * B^i)")
+                .takes_value(true)
+                .multiple(true)
+                .use_delimiter(true)
+                .possible_values(&["left", "right"])
+                .value_name("DIR")
+                .default_value("left")
+                .display_order(100),
+        )
+        .arg(
+            Arg::with_name("initial")
+                .long("initial")
+                .help("Initial value of each hash")
+                .takes_value(true)
+                .multiple(true)
+                .use_delimiter(true)
+                .value_name("VALUE")
+                .default_value("0")
+                .validator(is_valid::<u64>)
+                .display_order(100),
+        )
+        .arg(
+            Arg::with_name("length-term")
+                .long("length-term")
+                .help("Add the length of the string to the hashes")
+                .display_order(100),
+        )
+        .arg(
+            Arg::with_name("signed")
+                .long("signed")
+                .help("Compute the hashes with wrapping signed integers and the C remainder, MODULE 0 meaning none")
+                .takes_value(true)
+                .value_name("BITS")
+                .possible_values(&["32", "64"])
+                .display_order(100),
+        )
+        .arg(
+            Arg::with_name("final-mod")
+                .long("final-mod")
+                .help("With --signed, take the remainder only once at the end instead of after every step")
+                .requires("signed")
+                .display_order(100),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .help("Seed of the random number generator")
+                .takes_value(true)
+                .value_name("SEED")
+                .validator(is_valid::<u64>)
+                .display_order(100),
+        )
+        .arg(
+            Arg::with_name("timeout")
+                .long("timeout")
+                .help("Maximum running time of the attack, in seconds")
+                .takes_value(true)
+                .value_name("SECONDS")
+                .validator(is_valid::<u64>)
+                .display_order(100),
+        )
+        .arg(
+            Arg::with_name("max-memory")
+                .long("max-memory")
+                .help("Maximum memory used by the attack, in megabytes")
+                .takes_value(true)
+                .value_name("MB")
+                .validator(is_valid::<usize>)
+                .display_order(100),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .long("verbose")
+                .help("Print the progress of the attack to stderr")
+                .display_order(100),
+        )
+        .arg(
+            Arg::with_name("stats")
+                .short("s")
+                .long("stats")
+                .help("Print hashes and search statistics to stderr")
+                .display_order(100),
+        )
         .subcommand(
             SubCommand::with_name("overflow")
                 .about("Overflow attack")
@@ -78,11 +578,52 @@ fn main() {
                     Arg::with_name("length")
                         .short("l")
                         .long("length")
-                        .help("Minimum length of strings")
+                        .help("Length of strings, the shortest valid length if missing")
                         .takes_value(true)
                         .value_name("LENGTH")
-                        .default_value("1024")
                         .validator(is_valid::<usize>),
+                )
+                .arg(
+                    Arg::with_name("filler")
+                        .short("f")
+                        .long("filler")
+                        .help("Character used to pad the strings up to LENGTH")
+                        .takes_value(true)
+                        .value_name("CHAR")
+                        .validator(is_valid::<char>),
+                )
+                .arg(
+                    Arg::with_name("alphabet")
+                        .help("Pair of strings used as alphabet to build collision")
+                        .takes_value(true)
+                        .last(true)
+                        .number_of_values(2)
+                        .value_names(&["WORD1", "WORD2"])
+                        .validator(WordValidator::boxed()),
+                )
+                .arg(
+                    Arg::with_name("base")
+                        .short("B")
+                        .long("base")
+                        .help("Bases of the hashes, the worst case odd base is assumed if missing")
+                        .takes_value(true)
+                        .multiple(true)
+                        .use_delimiter(true)
+                        .value_name("BASE")
+                        .validator(is_valid::<u64>),
+                )
+                .arg(
+                    Arg::with_name("bits")
+                        .short("b")
+                        .long("bits")
+                        .help("Size of the machine word, the hash is computed modulo 2^BITS")
+                        .takes_value(true)
+                        .value_name("BITS")
+                        .default_value("64")
+                        .validator(|s| match s.parse::<u32>() {
+                            Ok(1..=128) => Ok(()),
+                            _ => Err("expected an integer between 1 and 128".to_string()),
+                        }),
                 ),
         )
         .subcommand(
@@ -91,13 +632,14 @@ fn main() {
                 .arg(
                     Arg::with_name("coefficients")
                         .help("Base and module of hash")
-                        .required(true)
+                        .required_unless("preset")
                         .takes_value(true)
                         .number_of_values(2)
                         .multiple(true)
                         .value_names(&["BASE", "MODULE"])
-                        .validator(is_valid::<u32>),
+                        .validator(is_valid::<u64>),
                 )
+                .arg(preset_arg())
                 .arg(
                     Arg::with_name("alphabet")
                         .help("String used as alphabet to build collision")
@@ -105,7 +647,28 @@ fn main() {
                         .last(true)
                         .multiple(true)
                         .value_name("WORDS")
-                        .validator(WordValidator::new()),
+                        .validator(WordValidator::boxed()),
+                )
+                .arg(
+                    Arg::with_name("strategy")
+                        .long("strategy")
+                        .help("Store samples, or walk to distinguished points, picked from the moduli if missing")
+                        .takes_value(true)
+                        .value_name("STRATEGY")
+                        .possible_values(&["table", "distinguished"]),
+                )
+                .arg(
+                    Arg::with_name("threads")
+                        .short("j")
+                        .long("threads")
+                        .help("Number of parallel walks of the distinguished strategy, not reproducible if above 1")
+                        .takes_value(true)
+                        .value_name("THREADS")
+                        .default_value("1")
+                        .validator(|s| match s.parse::<usize>() {
+                            Ok(0) | Err(_) => Err("expected a positive integer".to_string()),
+                            Ok(_) => Ok(()),
+                        }),
                 ),
         )
         .subcommand(
@@ -114,13 +677,14 @@ fn main() {
                 .arg(
                     Arg::with_name("coefficients")
                         .help("Base and module of hash")
-                        .required(true)
+                        .required_unless("preset")
                         .takes_value(true)
                         .number_of_values(2)
                         .multiple(true)
                         .validator(is_valid::<u64>)
                         .value_names(&["BASE", "MODULE"]),
                 )
+                .arg(preset_arg())
                 .arg(
                     Arg::with_name("alphabet")
                         .help("String used as alphabet to build collision")
@@ -128,7 +692,7 @@ fn main() {
                         .last(true)
                         .multiple(true)
                         .value_name("WORDS")
-                        .validator(WordValidator::new()),
+                        .validator(WordValidator::boxed()),
                 )
                 .arg(
                     Arg::with_name("cluster")
@@ -139,74 +703,231 @@ fn main() {
                         .value_name("SIZE")
                         .default_value("100000")
                         .validator(is_valid::<usize>),
+                )
+                .arg(
+                    Arg::with_name("joint")
+                        .short("J")
+                        .long("joint")
+                        .help("Cancel hashes with coprime moduli in a single tree instead of chaining them"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("lattice")
+                .about("Lattice reduction attack")
+                .arg(
+                    Arg::with_name("coefficients")
+                        .help("Base and module of hash")
+                        .required_unless("preset")
+                        .takes_value(true)
+                        .number_of_values(2)
+                        .multiple(true)
+                        .validator(is_valid::<u64>)
+                        .value_names(&["BASE", "MODULE"]),
+                )
+                .arg(preset_arg())
+                .arg(
+                    Arg::with_name("alphabet")
+                        .help("Characters used as alphabet to build collision")
+                        .takes_value(true)
+                        .last(true)
+                        .multiple(true)
+                        .value_name("CHARS")
+                        .validator(is_valid::<char>),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("verify")
+                .about("Check that two strings have the same hash")
+                .arg(
+                    Arg::with_name("coefficients")
+                        .help("Base and module of hash")
+                        .required_unless("preset")
+                        .takes_value(true)
+                        .number_of_values(2)
+                        .multiple(true)
+                        .validator(is_valid::<u64>)
+                        .value_names(&["BASE", "MODULE"]),
+                )
+                .arg(preset_arg())
+                .arg(
+                    Arg::with_name("files")
+                        .help("Files containing the two strings, read from stdin (one per line) if missing")
+                        .takes_value(true)
+                        .last(true)
+                        .number_of_values(2)
+                        .value_names(&["FILE1", "FILE2"]),
                 ),
         )
-        .get_matches();
+        .subcommand(SubCommand::with_name("presets").about("List the known hash presets"))
+        .subcommand(
+            SubCommand::with_name("unordered-map")
+                .about("Integer keys falling in the same bucket of a libstdc++ std::unordered_map")
+                .args(&key_range_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("hash-map")
+                .about("Distinct strings with the same hash, flooding a hash table such as a Java HashMap<String, T>")
+                .arg(
+                    Arg::with_name("coefficients")
+                        .help("Base and module of hash")
+                        .required_unless("preset")
+                        .takes_value(true)
+                        .number_of_values(2)
+                        .multiple(true)
+                        .value_names(&["BASE", "MODULE"])
+                        .validator(is_valid::<u64>),
+                )
+                .arg(preset_arg())
+                .arg(
+                    Arg::with_name("count")
+                        .short("n")
+                        .long("count")
+                        .help("Number of distinct strings")
+                        .required(true)
+                        .takes_value(true)
+                        .value_name("COUNT")
+                        .validator(is_valid::<usize>),
+                )
+                .arg(
+                    Arg::with_name("max-length")
+                        .short("l")
+                        .long("max-length")
+                        .help("Largest allowed length of the strings")
+                        .takes_value(true)
+                        .value_name("LENGTH")
+                        .default_value("1000")
+                        .validator(is_valid::<usize>),
+                )
+                .arg(
+                    Arg::with_name("alphabet")
+                        .help("String used as alphabet to build the strings")
+                        .takes_value(true)
+                        .last(true)
+                        .multiple(true)
+                        .value_name("WORDS")
+                        .validator(WordValidator::boxed()),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("pbds")
+                .about("Integer keys falling in the same bucket of a pb_ds gp_hash_table or cc_hash_table")
+                .args(&key_range_args())
+                .arg(
+                    Arg::with_name("policy")
+                        .long("policy")
+                        .help("Resize policy of the table")
+                        .takes_value(true)
+                        .possible_values(&["mask", "prime"])
+                        .default_value("mask"),
+                )
+                .arg(
+                    Arg::with_name("hash")
+                        .long("hash")
+                        .help("Hash of the keys: identity, xor:C or add:C")
+                        .takes_value(true)
+                        .value_name("HASH")
+                        .default_value("identity")
+                        .validator(is_valid::<KeyHash>),
+                ),
+        )
+}
+
+fn main() {
+    let matches = app().get_matches();
 
-    let coll = match matches.subcommand() {
+    let options = HashOptions::from_matches(&matches);
+    let (attack, target): (Box<dyn Attack>, HashSpec) = match matches.subcommand() {
         ("overflow", Some(submatches)) => {
-            let len = submatches.value_of("length").unwrap().parse::<usize>().unwrap().next_power_of_two();
-            overflow_attack::find_collision(len)
-        }
-        ("birthday", Some(submatches)) => {
-            let mut bases = Vec::new();
-            let mut modules = Vec::new();
-            for (index, coefficient) in submatches.values_of("coefficients").unwrap().enumerate() {
-                if index % 2 == 0 {
-                    bases.push(coefficient.parse().unwrap());
-                } else {
-                    modules.push(coefficient.parse().unwrap());
-                }
-            }
+            let length = submatches.value_of("length").map(|l| l.parse().unwrap());
+            let bits = submatches.value_of("bits").unwrap().parse().unwrap();
+            let filler = submatches.value_of("filler").map(|f| f.parse().unwrap());
             let alphabet = match submatches.values_of("alphabet") {
                 Some(a) => a.map(|s| s.to_string()).collect(),
-                None => (0..26)
-                    .map(|i| std::char::from_u32(i + 97).unwrap().to_string())
-                    .collect(),
+                None => OverflowAttack::default().alphabet,
+            };
+            if bits > 64 {
+                eprintln!("Hashes with more than 64 bits are only checked modulo 2^64");
+            }
+            let modulus = if bits < 64 { 1u64 << bits } else { 0 };
+            let hashes = match submatches.values_of("base") {
+                Some(bases) => bases.enumerate().map(|(i, b)| options.build(i, b.parse().unwrap(), modulus)).collect(),
+                None => Vec::new(),
+            };
+            let target = HashSpec::new(hashes);
+            let attack = OverflowAttack {
+                length,
+                bits,
+                alphabet,
+                filler,
+            };
+            eprintln!("Minimal length for 2^{}: {}", bits, attack.min_length(&target));
+            (Box::new(attack), target)
+        }
+        ("birthday", Some(submatches)) => {
+            let alphabet = get_alphabet(submatches);
+            let target = get_hash_spec(submatches, &options);
+            let strategy = match submatches.value_of("strategy") {
+                Some("table") => Strategy::Table,
+                Some(_) => Strategy::DistinguishedPoints,
+                None => Strategy::for_hashes(&target.hashes),
             };
-            birthday_attack::find_collision(bases, modules, alphabet)
+            let threads = submatches.value_of("threads").unwrap().parse().unwrap();
+            let attack = BirthdayAttack {
+                alphabet,
+                strategy,
+                threads,
+            };
+            (Box::new(attack), target)
         }
         ("tree", Some(submatches)) => {
-            let mut bases = Vec::new();
-            let mut modules = Vec::new();
-            for (index, coefficient) in submatches.values_of("coefficients").unwrap().enumerate() {
-                if index % 2 == 0 {
-                    bases.push(coefficient.parse().unwrap());
-                } else {
-                    modules.push(coefficient.parse().unwrap());
-                }
-            }
             let cluster_size = submatches.value_of("cluster").unwrap().parse().unwrap();
-            let alphabet = match submatches.values_of("alphabet") {
-                Some(a) => a.map(|s| s.to_string()).collect(),
-                None => (0..26)
-                    .map(|i| std::char::from_u32(i + 97).unwrap().to_string())
-                    .collect(),
+            let alphabet = get_alphabet(submatches);
+            let attack = TreeAttack {
+                cluster_size,
+                alphabet,
+                joint: submatches.is_present("joint"),
             };
-            tree_attack::find_collision(bases, modules, cluster_size, alphabet)
+            (Box::new(attack), get_hash_spec(submatches, &options))
+        }
+        ("lattice", Some(submatches)) => {
+            let alphabet = get_alphabet(submatches);
+            (Box::new(LatticeAttack { alphabet }), get_hash_spec(submatches, &options))
+        }
+        ("verify", Some(submatches)) => process::exit(verify(submatches, &options)),
+        ("unordered-map", Some(submatches)) => process::exit(unordered_map(submatches)),
+        ("pbds", Some(submatches)) => process::exit(pbds(submatches)),
+        ("hash-map", Some(submatches)) => process::exit(hash_map(&matches, submatches, &options)),
+        ("presets", Some(_)) => {
+            print_presets();
+            process::exit(0)
         }
-        _ => None,
+        _ => unreachable!(),
     };
+    let mut ctx = get_context(&matches);
+    let coll = attack.run(&target, &mut ctx);
 
-    if let Some((mut fi, mut se)) = coll {
-        if matches.is_present("reverse") {
-            fi = fi.chars().rev().collect::<String>();
-            se = se.chars().rev().collect::<String>();
+    let check = match matches.subcommand() {
+        ("overflow", Some(submatches)) if target.hashes.is_empty() => {
+            overflow_check(submatches.value_of("bits").unwrap().parse().unwrap(), &options)
         }
-        if matches.is_present("uppercase") {
-            fi.make_ascii_uppercase();
-            se.make_ascii_uppercase();
+        _ => target.clone(),
+    };
+    match coll {
+        Ok(coll) => process::exit(print_collision(&matches, &target, &check, coll)),
+        Err(e) => {
+            eprintln!("Collision not found: {}", e);
+            process::exit(1);
         }
-
-        println!("{}\n{}", fi, se);
-    } else {
-        println!("Collision not found");
     }
 }
 
+#[cfg(test)]
+use antihash::{birthday_attack, lattice_attack, overflow_attack, tree_attack};
+
 #[test]
 fn overflow_attack() {
-    let (s1, s2) = overflow_attack::find_collision(1024).expect("collision not found");
+    let coll = overflow_attack::find_collision(1024).expect("collision not found");
+    let (s1, s2) = (coll.first, coll.second);
     let (mut h1, mut h2) = (0u64, 0u64);
     let base = 9973;
     for c1 in s1.chars() {
@@ -216,6 +937,14 @@ fn overflow_attack() {
         h2 = h2.wrapping_mul(base).wrapping_add(c2 as u64);
     }
     assert!(h1 == h2, "hashes are different");
+    assert!(matches!(overflow_attack::find_collision(1000), Err(AttackError::InvalidParameter(_))));
+    let words = |w: &[&str]| w.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    assert!(overflow_attack::thue_morse(&words(&["a"]), 4, 0, 4, 'a').is_err());
+    assert!(overflow_attack::thue_morse(&words(&["a", "bc"]), 4, 0, 8, 'a').is_err());
+    assert!(overflow_attack::thue_morse(&words(&["a", "b", "c"]), 4, 0, 4, 'a').is_err());
+    let target = HashSpec::new(vec![PolynomialHash::new(31, 1000000007)]);
+    let result = OverflowAttack::default().run(&target, &mut Context::default());
+    assert!(matches!(result, Err(AttackError::InvalidHash(_))));
 }
 
 #[test]
@@ -225,7 +954,10 @@ fn birthday_attack() {
     let alphabet = (0..26)
         .map(|i| std::char::from_u32(i + 97).unwrap().to_string())
         .collect();
-    let (s1, s2) = birthday_attack::find_collision(vec![base], vec![module], alphabet).expect("collision not found");
+    let mut ctx = Context::default();
+    let coll = birthday_attack::find_collision(&[PolynomialHash::new(base, module)], alphabet, &mut ctx)
+        .expect("collision not found");
+    let (s1, s2) = (coll.first, coll.second);
     let (mut h1, mut h2) = (0u64, 0u64);
     for c1 in s1.chars() {
         h1 = (h1 * base + c1 as u64) % module;
@@ -238,12 +970,15 @@ fn birthday_attack() {
 
 #[test]
 fn birthday_attack_multiple() {
-    let bases = vec![9973, 11173];
-    let modules = vec![1000000007, 1000000009];
+    let bases = [9973, 11173];
+    let modules = [1000000007, 1000000009];
+    let hashes: Vec<_> = bases.iter().zip(modules.iter()).map(|(&b, &m)| PolynomialHash::new(b, m)).collect();
     let alphabet = (0..26)
         .map(|i| std::char::from_u32(i + 97).unwrap().to_string())
         .collect();
-    let (s1, s2) = birthday_attack::find_collision(bases.clone(), modules.clone(), alphabet).expect("collision not found");
+    let mut ctx = Context::default();
+    let coll = birthday_attack::find_collision(&hashes, alphabet, &mut ctx).expect("collision not found");
+    let (s1, s2) = (coll.first, coll.second);
     for (&b, &m) in bases.iter().zip(modules.iter()) {
         let (mut h1, mut h2) = (0u64, 0u64);
         for c1 in s1.chars() {
@@ -261,7 +996,10 @@ fn birthday_attack_alphabet() {
     let base = 9973;
     let module = 1000000007;
     let alphabet = vec!["xcphdx".to_string(), "fsngso".to_string()];
-    let (s1, s2) = birthday_attack::find_collision(vec![base], vec![module], alphabet).expect("collision not found");
+    let mut ctx = Context::default();
+    let coll = birthday_attack::find_collision(&[PolynomialHash::new(base, module)], alphabet, &mut ctx)
+        .expect("collision not found");
+    let (s1, s2) = (coll.first, coll.second);
     let (mut h1, mut h2) = (0u64, 0u64);
     for c1 in s1.chars() {
         h1 = (h1 * base + c1 as u64) % module;
@@ -279,7 +1017,10 @@ fn tree_attack() {
     let alphabet = (0..26)
         .map(|i| std::char::from_u32(i + 97).unwrap().to_string())
         .collect();
-    let (s1, s2) = tree_attack::find_collision(vec![base], vec![module], 100000, alphabet).expect("collision not found");
+    let mut ctx = Context::default();
+    let coll = tree_attack::find_collision(&[PolynomialHash::new(base, module)], 100000, alphabet, &mut ctx)
+        .expect("collision not found");
+    let (s1, s2) = (coll.first, coll.second);
     let (mut h1, mut h2) = (0u64, 0u64);
     for c1 in s1.chars() {
         h1 = (h1 * base + c1 as u64) % module;
@@ -292,12 +1033,16 @@ fn tree_attack() {
 
 #[test]
 fn tree_attack_multiple() {
-    let bases = vec![9973, 11173];
-    let modules = vec![1000000007, 1000000009];
+    let bases = [9973, 11173];
+    let modules = [1000000007, 1000000009];
+    let hashes: Vec<_> = bases.iter().zip(modules.iter()).map(|(&b, &m)| PolynomialHash::new(b, m)).collect();
     let alphabet = (0..26)
         .map(|i| std::char::from_u32(i + 97).unwrap().to_string())
         .collect();
-    let (s1, s2) = tree_attack::find_collision(bases.clone(), modules.clone(), 100000, alphabet).expect("collision not found");
+    let mut ctx = Context::default();
+    let coll = tree_attack::find_collision(&hashes, 100000, alphabet, &mut ctx)
+        .expect("collision not found");
+    let (s1, s2) = (coll.first, coll.second);
     for (&b, &m) in bases.iter().zip(modules.iter()) {
         let (mut h1, mut h2) = (0u64, 0u64);
         for c1 in s1.chars() {
@@ -315,7 +1060,10 @@ fn tree_attack_alphabet() {
     let base = 9973;
     let module = 1000000007;
     let alphabet = vec!["xcphdx".to_string(), "fsngso".to_string()];
-    let (s1, s2) = tree_attack::find_collision(vec![base], vec![module], 100000, alphabet).expect("collision not found");
+    let mut ctx = Context::default();
+    let coll = tree_attack::find_collision(&[PolynomialHash::new(base, module)], 100000, alphabet, &mut ctx)
+        .expect("collision not found");
+    let (s1, s2) = (coll.first, coll.second);
     let (mut h1, mut h2) = (0u64, 0u64);
     for c1 in s1.chars() {
         h1 = (h1 * base + c1 as u64) % module;
@@ -325,3 +1073,573 @@ fn tree_attack_alphabet() {
     }
     assert!(h1 == h2, "hashes are different");
 }
+
+#[test]
+fn attack_trait() {
+    let target = HashSpec::new(vec![PolynomialHash::new(9973, 1000000007)]);
+    let attacks: Vec<Box<dyn Attack>> = vec![Box::new(BirthdayAttack::default()), Box::new(TreeAttack::default())];
+    for attack in attacks {
+        let coll = attack.run(&target, &mut Context::default()).expect("collision not found");
+        let (s1, s2) = (coll.first, coll.second);
+        let (mut h1, mut h2) = (0u64, 0u64);
+        for c1 in s1.chars() {
+            h1 = (h1 * 9973 + c1 as u64) % 1000000007;
+        }
+        for c2 in s2.chars() {
+            h2 = (h2 * 9973 + c2 as u64) % 1000000007;
+        }
+        assert!(h1 == h2, "hashes are different for {} attack", attack.name());
+    }
+}
+
+#[test]
+fn polynomial_hash() {
+    let mut hash = PolynomialHash::new(9973, 1000000007);
+    hash.initial = 17;
+    let s = "antihash";
+    let (mut left, mut right, mut pot) = (17u64, 17u64, 1u64);
+    for c in s.chars() {
+        left = (left * 9973 + c as u64) % 1000000007;
+        right = (right + c as u64 * pot) % 1000000007;
+        pot = pot * 9973 % 1000000007;
+    }
+    assert_eq!(hash.evaluate(s), left);
+    hash.direction = antihash::Direction::RightToLeft;
+    assert_eq!(hash.evaluate(s), right);
+}
+
+#[test]
+fn attack_errors() {
+    use antihash::AttackError;
+    let hashes = [PolynomialHash::new(9973, 1000000007)];
+    let mut ctx = Context::default();
+    let err = birthday_attack::find_collision(&hashes, vec!["a".to_string(), "a".to_string()], &mut ctx);
+    assert_eq!(err.unwrap_err(), AttackError::AlphabetTooSmall);
+    let err = tree_attack::find_collision(&hashes, 100000, vec!["a".to_string(), "bc".to_string()], &mut ctx);
+    assert_eq!(err.unwrap_err(), AttackError::InvalidAlphabet);
+    let hashes = [PolynomialHash::new(9973, 1000000000000000003)];
+    let err = tree_attack::find_collision(&hashes, 1, vec!["a".to_string(), "b".to_string()], &mut ctx);
+    assert!(matches!(err, Err(AttackError::LengthLimitExceeded { attack: "tree", .. })));
+}
+
+#[test]
+fn collision_stats() {
+    let hashes = [PolynomialHash::new(9973, 1000000007), PolynomialHash::new(11173, 1000000009)];
+    let mut ctx = Context::default();
+    let coll = birthday_attack::find_collision(&hashes, antihash::default_alphabet(), &mut ctx)
+        .expect("collision not found");
+    assert_eq!(coll.attack, "birthday");
+    assert_eq!(coll.length, coll.first.len());
+    assert_eq!(coll.depths.len(), 2);
+    assert!(coll.steps > 0);
+    for (hash, &value) in hashes.iter().zip(coll.hashes.iter()) {
+        assert_eq!(hash.evaluate(&coll.first), value);
+        assert_eq!(hash.evaluate(&coll.second), value);
+    }
+}
+
+#[test]
+fn reproducible_seed() {
+    let target = HashSpec::new(vec![PolynomialHash::new(9973, 1000000007)]);
+    let c1 = BirthdayAttack::default().run(&target, &mut Context::new(42)).expect("collision not found");
+    let c2 = BirthdayAttack::default().run(&target, &mut Context::new(42)).expect("collision not found");
+    assert_eq!((c1.first, c1.second), (c2.first, c2.second));
+}
+
+#[test]
+fn progress_observer() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let observed = events.clone();
+    let mut ctx = Context::new(42).with_observer(move |p| observed.borrow_mut().push(p.clone()));
+    let attack = TreeAttack {
+        cluster_size: 1000,
+        ..TreeAttack::default()
+    };
+    attack.run(&HashSpec::new(vec![PolynomialHash::new(9973, 1000003)]), &mut ctx).expect("collision not found");
+    let events = events.borrow();
+    assert!(!events.is_empty());
+    assert!(events.iter().all(|p| matches!(p, Progress::Tree { fill, .. } if *fill <= 1000)));
+}
+
+#[test]
+fn attack_budgets() {
+    use antihash::AttackError;
+    use std::time::Duration;
+    let target = HashSpec::new(vec![PolynomialHash::new(9973, 1000000000000000003)]);
+    let mut ctx = Context::new(42).with_max_memory(1 << 20);
+    let err = TreeAttack::default().run(&target, &mut ctx);
+    assert!(matches!(err, Err(AttackError::MemoryLimitExceeded { .. })));
+    let mut ctx = Context::new(42).with_timeout(Duration::from_millis(100));
+    let err = TreeAttack::default().run(&target, &mut ctx);
+    assert_eq!(err.unwrap_err(), AttackError::Timeout);
+}
+
+#[test]
+fn char_map() {
+    let table = CharMap::Table([('a', 3), ('b', -1)].iter().cloned().collect());
+    assert_eq!(table.value('a'), 3);
+    assert_eq!(table.value('c'), 'c' as i128);
+    assert_eq!(CharMap::Offset(1).value('a'), 1);
+    let hash = PolynomialHash {
+        char_map: table,
+        ..PolynomialHash::new(9973, 1000000007)
+    };
+    assert_eq!(hash.char_value('b'), 1000000006);
+    let mut ctx = Context::default();
+    let alphabet = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+    let coll = birthday_attack::find_collision(&[hash], alphabet, &mut ctx).expect("collision not found");
+    let (mut h1, mut h2) = (0i64, 0i64);
+    let value = |c| match c {
+        'a' => 3,
+        'b' => -1,
+        c => c as i64,
+    };
+    for c1 in coll.first.chars() {
+        h1 = (h1 * 9973 + value(c1)).rem_euclid(1000000007);
+    }
+    for c2 in coll.second.chars() {
+        h2 = (h2 * 9973 + value(c2)).rem_euclid(1000000007);
+    }
+    assert!(h1 == h2, "hashes are different");
+}
+
+#[test]
+fn tree_attack_mixed_directions() {
+    let left = PolynomialHash::new(9973, 1000000007);
+    let right = PolynomialHash {
+        direction: Direction::RightToLeft,
+        ..PolynomialHash::new(11173, 1000000009)
+    };
+    let mut ctx = Context::default();
+    let coll = tree_attack::find_collision(&[left, right], 100000, antihash::default_alphabet(), &mut ctx)
+        .expect("collision not found");
+    let (s1, s2) = (coll.first, coll.second);
+    let (mut h1, mut h2) = (0u64, 0u64);
+    for (c1, c2) in s1.chars().zip(s2.chars()) {
+        h1 = (h1 * 9973 + c1 as u64) % 1000000007;
+        h2 = (h2 * 9973 + c2 as u64) % 1000000007;
+    }
+    assert!(h1 == h2, "hashes are different");
+    let (mut h1, mut h2) = (0u64, 0u64);
+    for (c1, c2) in s1.chars().rev().zip(s2.chars().rev()) {
+        h1 = (h1 * 11173 + c1 as u64) % 1000000009;
+        h2 = (h2 * 11173 + c2 as u64) % 1000000009;
+    }
+    assert!(h1 == h2, "hashes are different");
+}
+
+#[test]
+fn birthday_attack_initial_value() {
+    let hash = PolynomialHash {
+        initial: 5381,
+        length_term: true,
+        ..PolynomialHash::new(33, 1 << 32)
+    };
+    let mut ctx = Context::default();
+    let coll = birthday_attack::find_collision(&[hash], antihash::default_alphabet(), &mut ctx)
+        .expect("collision not found");
+    let (mut h1, mut h2) = (5381u32, 5381u32);
+    for c1 in coll.first.chars() {
+        h1 = h1.wrapping_mul(33).wrapping_add(c1 as u32);
+    }
+    for c2 in coll.second.chars() {
+        h2 = h2.wrapping_mul(33).wrapping_add(c2 as u32);
+    }
+    h1 = h1.wrapping_add(coll.first.len() as u32);
+    h2 = h2.wrapping_add(coll.second.len() as u32);
+    assert!(h1 == h2, "hashes are different");
+    assert_eq!(coll.hashes, vec![h1 as u64]);
+}
+
+#[test]
+fn overflow_attack_32() {
+    let length = overflow_attack::min_length(32);
+    let coll = overflow_attack::find_collision(length).expect("collision not found");
+    for &base in &[3, 31, 9973] {
+        let (mut h1, mut h2) = (0u32, 0u32);
+        for (c1, c2) in coll.first.chars().zip(coll.second.chars()) {
+            h1 = h1.wrapping_mul(base).wrapping_add(c1 as u32);
+            h2 = h2.wrapping_mul(base).wrapping_add(c2 as u32);
+        }
+        assert!(h1 == h2, "hashes are different");
+    }
+    let coll = overflow_attack::find_collision(length / 2).expect("collision not found");
+    let (mut h1, mut h2) = (0u32, 0u32);
+    for (c1, c2) in coll.first.chars().zip(coll.second.chars()) {
+        h1 = h1.wrapping_mul(3).wrapping_add(c1 as u32);
+        h2 = h2.wrapping_mul(3).wrapping_add(c2 as u32);
+    }
+    assert!(h1 != h2, "length is not minimal");
+}
+
+#[test]
+fn overflow_attack_128() {
+    let coll = overflow_attack::find_collision(overflow_attack::min_length(128)).expect("collision not found");
+    for &base in &[3, 31, 9973] {
+        let (mut h1, mut h2) = (0u128, 0u128);
+        for (c1, c2) in coll.first.chars().zip(coll.second.chars()) {
+            h1 = h1.wrapping_mul(base).wrapping_add(c1 as u128);
+            h2 = h2.wrapping_mul(base).wrapping_add(c2 as u128);
+        }
+        assert!(h1 == h2, "hashes are different");
+    }
+}
+
+#[test]
+fn overflow_attack_alphabet() {
+    let attack = OverflowAttack {
+        length: Some(7000),
+        bits: 64,
+        alphabet: vec!["xcphdx".to_string(), "fsngso".to_string()],
+        filler: Some('z'),
+    };
+    let coll = attack.run(&HashSpec::default(), &mut Context::default()).expect("collision not found");
+    assert_eq!((coll.first.len(), coll.second.len()), (7000, 7000));
+    assert!(coll.first.ends_with("zzz"));
+    let (mut h1, mut h2) = (0u64, 0u64);
+    for (c1, c2) in coll.first.chars().zip(coll.second.chars()) {
+        h1 = h1.wrapping_mul(9973).wrapping_add(c1 as u64);
+        h2 = h2.wrapping_mul(9973).wrapping_add(c2 as u64);
+    }
+    assert!(h1 == h2, "hashes are different");
+    let attack = OverflowAttack {
+        length: Some(1000),
+        ..OverflowAttack::default()
+    };
+    let err = attack.run(&HashSpec::default(), &mut Context::default());
+    assert_eq!(err.unwrap_err(), antihash::AttackError::LengthTooShort { min_length: 1024 });
+}
+
+#[test]
+fn overflow_attack_even_base() {
+    let modulus = 1u64 << 32;
+    let target = HashSpec::new(vec![PolynomialHash::new(6, modulus), PolynomialHash::new(3 << 20, modulus)]);
+    let attack = OverflowAttack {
+        bits: 32,
+        ..OverflowAttack::default()
+    };
+    assert_eq!(attack.min_length(&target), 1 + 32);
+    let coll = attack.run(&target, &mut Context::default()).expect("collision not found");
+    assert_eq!((coll.first.as_str(), coll.second.len()), ("a".repeat(33).as_str(), 33));
+    assert_eq!(coll.hashes, target.evaluate(&coll.second));
+    assert_ne!(coll.first, coll.second);
+
+    let mut hash = PolynomialHash::new(1 << 40, 0);
+    hash.direction = Direction::RightToLeft;
+    let target = HashSpec::new(vec![hash, PolynomialHash::new(9973, 0)]);
+    let coll = OverflowAttack::default().run(&target, &mut Context::default()).expect("collision not found");
+    assert_eq!(coll.first.len(), 2 + overflow_attack::min_blocks(9973, 1, 64));
+    assert!(coll.first.starts_with("aa"));
+    assert_eq!(coll.hashes, target.evaluate(&coll.second));
+    assert_eq!(overflow_attack::min_padding(1 << 40, 64), 2);
+    assert_eq!(overflow_attack::min_padding(0, 128), 1);
+}
+
+#[test]
+fn tree_attack_64_bit_moduli() {
+    let base = 31u128;
+    for &module in &[(1u64 << 61) - 1, 9223372036854775783] {
+        let mut ctx = Context::new(1);
+        let coll = tree_attack::find_collision(&[PolynomialHash::new(31, module)], 20000, default_alphabet(), &mut ctx)
+            .expect("collision not found");
+        assert_ne!(coll.first, coll.second);
+        let (mut h1, mut h2) = (0u128, 0u128);
+        for (c1, c2) in coll.first.chars().zip(coll.second.chars()) {
+            h1 = (h1 * base + c1 as u128) % module as u128;
+            h2 = (h2 * base + c2 as u128) % module as u128;
+        }
+        assert!(h1 == h2, "hashes are different");
+    }
+}
+
+#[test]
+fn birthday_attack_distinguished() {
+    let attack = BirthdayAttack {
+        strategy: Strategy::DistinguishedPoints,
+        threads: 2,
+        ..BirthdayAttack::default()
+    };
+    let targets = [vec![(9973u128, 1000000007u128), (11173, 1000000009)], vec![(31, 1000000000039)]];
+    for target in &targets {
+        let hashes = target.iter().map(|&(b, m)| PolynomialHash::new(b as u64, m as u64)).collect();
+        let coll = attack.run(&HashSpec::new(hashes), &mut Context::default()).expect("collision not found");
+        assert_ne!(coll.first, coll.second);
+        for &(b, m) in target {
+            let (mut h1, mut h2) = (0u128, 0u128);
+            for (c1, c2) in coll.first.chars().zip(coll.second.chars()) {
+                h1 = (h1 * b + c1 as u128) % m;
+                h2 = (h2 * b + c2 as u128) % m;
+            }
+            assert!(h1 == h2, "hashes are different");
+        }
+    }
+    let attack = BirthdayAttack {
+        threads: 0,
+        ..attack
+    };
+    assert!(attack.run(&HashSpec::default(), &mut Context::default()).is_err());
+    let large = vec![PolynomialHash::new(31, 1000000000000000003)];
+    assert_eq!(Strategy::for_hashes(&large), Strategy::DistinguishedPoints);
+    assert_eq!(Strategy::for_hashes(&[PolynomialHash::new(31, 1000000007)]), Strategy::Table);
+    let table = BirthdayAttack::default().run(&HashSpec::new(large), &mut Context::default());
+    assert!(matches!(table, Err(AttackError::InvalidParameter(_))));
+    for &modulus in &[1, 2, 3] {
+        let target = HashSpec::new(vec![PolynomialHash::new(31, modulus)]);
+        for attack in &[BirthdayAttack::default(), BirthdayAttack { threads: 2, ..attack.clone() }] {
+            let coll = attack.run(&target, &mut Context::default()).expect("collision not found");
+            assert_ne!(coll.first, coll.second);
+            assert_eq!(coll.length, 6);
+        }
+    }
+}
+
+#[test]
+fn lattice_attack() {
+    let bases = [31u128, 37, 41, 43];
+    let modules = [1000000007u128, 1000000009, 998244353, 1 << 64];
+    let mut hashes: Vec<_> =
+        bases.iter().zip(modules.iter()).map(|(&b, &m)| PolynomialHash::new(b as u64, m as u64)).collect();
+    hashes[2].direction = Direction::RightToLeft;
+    let coll = lattice_attack::find_collision(&hashes, &default_alphabet(), &mut Context::default())
+        .expect("collision not found");
+    assert_ne!(coll.first, coll.second);
+    for (i, (&b, &m)) in bases.iter().zip(modules.iter()).enumerate() {
+        let (mut h1, mut h2) = (0u128, 0u128);
+        let (mut s1, mut s2): (Vec<_>, Vec<_>) = (coll.first.chars().collect(), coll.second.chars().collect());
+        if i == 2 {
+            s1.reverse();
+            s2.reverse();
+        }
+        for (c1, c2) in s1.into_iter().zip(s2) {
+            h1 = (h1 * b + c1 as u128) % m;
+            h2 = (h2 * b + c2 as u128) % m;
+        }
+        assert!(h1 == h2, "hashes are different");
+    }
+    let attack = LatticeAttack {
+        alphabet: vec!["ab".to_string(), "ba".to_string()],
+    };
+    let target = HashSpec::new(vec![PolynomialHash::new(31, 1000000007)]);
+    assert!(attack.run(&target, &mut Context::default()).is_err());
+    let mut hashes = vec![PolynomialHash::new(31, 1000000007), PolynomialHash::new(37, 1000000009)];
+    hashes[0].char_map = CharMap::Offset(1);
+    assert!(lattice_attack::find_collision(&hashes, &default_alphabet(), &mut Context::default()).is_ok());
+    hashes[1].char_map = CharMap::Table([('a', 1), ('b', 3)].iter().copied().collect());
+    let result = lattice_attack::find_collision(&hashes, &default_alphabet(), &mut Context::default());
+    assert!(matches!(result, Err(AttackError::InvalidParameter(_))));
+
+    let mut hash = PolynomialHash::new(131, (1 << 61) - 1);
+    hash.char_map = CharMap::Table([('a', 0), ('b', 1 << 60)].iter().copied().collect());
+    let alphabet = vec!["a".to_string(), "b".to_string()];
+    let result = lattice_attack::find_collision(&[hash], &alphabet, &mut Context::default());
+    assert!(matches!(result, Err(AttackError::InvalidParameter(_))));
+}
+
+#[test]
+fn tree_attack_joint() {
+    let bases = [9973u128, 11173];
+    let modules = [1000000007u128, 1000000009];
+    let hashes: Vec<_> =
+        bases.iter().zip(modules.iter()).map(|(&b, &m)| PolynomialHash::new(b as u64, m as u64)).collect();
+    let mut ctx = Context::new(1);
+    let coll = tree_attack::find_joint_collision(&hashes, 20000, default_alphabet(), &mut ctx)
+        .expect("collision not found");
+    assert_eq!(coll.depths.len(), 2);
+    assert!(coll.length <= 64, "hashes were not cancelled in a single tree");
+    assert_ne!(coll.first, coll.second);
+    for (&b, &m) in bases.iter().zip(modules.iter()) {
+        let (mut h1, mut h2) = (0u128, 0u128);
+        for (c1, c2) in coll.first.chars().zip(coll.second.chars()) {
+            h1 = (h1 * b + c1 as u128) % m;
+            h2 = (h2 * b + c2 as u128) % m;
+        }
+        assert!(h1 == h2, "hashes are different");
+    }
+}
+
+#[test]
+fn presets() {
+    let java = preset::find("java-string").expect("missing preset").hash_spec();
+    assert_eq!(java.evaluate("Aa"), java.evaluate("BB"));
+    assert_eq!(preset::find("djb2").unwrap().hash_spec().evaluate(""), vec![5381]);
+    assert_eq!(preset::find("double-131").unwrap().hash_spec().hashes.len(), 2);
+    assert!(preset::find("unknown").is_none());
+    let matches = app().get_matches_from(["antihash", "--char-map", "offset:1", "--length-term", "tree", "-p", "djb2"]);
+    let target = get_hash_spec(matches.subcommand_matches("tree").unwrap(), &HashOptions::from_matches(&matches));
+    assert_eq!(target.hashes[0].char_map, CharMap::Offset(1));
+    assert!(target.hashes[0].length_term);
+    assert_eq!((target.hashes[0].base, target.hashes[0].initial), (33, 5381));
+    let matches = app().get_matches_from(["antihash", "tree", "-p", "djb2"]);
+    let target = get_hash_spec(matches.subcommand_matches("tree").unwrap(), &HashOptions::from_matches(&matches));
+    assert_eq!(target, preset::find("djb2").unwrap().hash_spec());
+    let target = preset::find("double-131").unwrap().hash_spec();
+    let coll = LatticeAttack::default().run(&target, &mut Context::new(42)).expect("collision not found");
+    assert_eq!(target.evaluate(&coll.first), target.evaluate(&coll.second));
+}
+
+#[test]
+fn signed_arithmetic() {
+    let java = preset::find("java-string").unwrap().hash_spec();
+    let mut ctx = Context::new(42);
+    let coll = TreeAttack::default().run(&java, &mut ctx).expect("collision not found");
+    let java_hash = |s: &str| s.chars().fold(0i32, |h, c| h.wrapping_mul(31).wrapping_add(c as i32));
+    assert_ne!(coll.first, coll.second);
+    assert_eq!(java_hash(&coll.first), java_hash(&coll.second));
+    assert_eq!(coll.hashes, vec![java_hash(&coll.first) as i64 as u64]);
+
+    let mut hash = PolynomialHash::new(31, 1000000007);
+    hash.arithmetic = Arithmetic::Signed {
+        bits: 32,
+        per_step: true,
+    };
+    assert!(hash.linearize().is_none());
+    let c_hash = |s: &str| s.chars().fold(0i32, |h, c| h.wrapping_mul(31).wrapping_add(c as i32) % 1000000007);
+    assert_eq!(hash.evaluate("zzzzzzzzzz"), c_hash("zzzzzzzzzz") as i64 as u64);
+    let target = HashSpec::new(vec![hash]);
+    assert!(TreeAttack::default().run(&target, &mut ctx).is_err());
+    let coll = BirthdayAttack::default().run(&target, &mut ctx).expect("collision not found");
+    assert_ne!(coll.first, coll.second);
+    assert_eq!(c_hash(&coll.first), c_hash(&coll.second));
+
+    for &bits in &[0, 65] {
+        let mut hash = PolynomialHash::new(31, 0);
+        hash.arithmetic = Arithmetic::Signed {
+            bits,
+            per_step: false,
+        };
+        assert!(hash.linearize().is_none());
+        let target = HashSpec::new(vec![hash]);
+        for attack in &[&TreeAttack::default() as &dyn Attack, &BirthdayAttack::default(), &LatticeAttack::default()] {
+            assert!(matches!(attack.run(&target, &mut ctx), Err(AttackError::InvalidHash(_))));
+        }
+    }
+}
+
+#[test]
+fn unordered_map_keys() {
+    assert_eq!(hash_table_attack::libstdcxx_bucket_count(0), Some(1));
+    assert_eq!(hash_table_attack::libstdcxx_bucket_count(1), Some(13));
+    assert_eq!(hash_table_attack::libstdcxx_bucket_count(14), Some(29));
+    assert_eq!(hash_table_attack::libstdcxx_bucket_count(100000), Some(172933));
+    for &(min, max) in &[(0, 1000000000000000000), (-1000000000000000000, -1), (-5000000000000, 5000000000000)] {
+        let set = hash_table_attack::libstdcxx_keys(100000, min, max).expect("keys not found");
+        assert_eq!(set.keys.len(), 100000);
+        assert!(set.bucket_counts.contains(&172933));
+        let distinct: std::collections::HashSet<_> = set.keys.iter().collect();
+        assert_eq!(distinct.len(), 100000);
+        for &key in &set.keys {
+            assert!(min <= key && key <= max);
+            assert!(set.bucket_counts.iter().all(|&b| (key as u64).is_multiple_of(b)));
+        }
+    }
+    assert!(hash_table_attack::libstdcxx_keys(100000, 0, 1000000000).is_err());
+}
+
+#[test]
+fn pbds_keys() {
+    use hash_table_attack::{KeyHash, SizePolicy};
+    assert_eq!(hash_table_attack::pbds_size(0, SizePolicy::Mask), Some(8));
+    assert_eq!(hash_table_attack::pbds_size(4, SizePolicy::Mask), Some(16));
+    assert_eq!(hash_table_attack::pbds_size(65536, SizePolicy::Mask), Some(262144));
+    assert_eq!(hash_table_attack::pbds_size(2, SizePolicy::Prime), Some(11));
+    assert_eq!(hash_table_attack::pbds_size(100000, SizePolicy::Prime), Some(236897));
+    assert_eq!("xor:0x10".parse(), Ok(KeyHash::Xor(16)));
+    assert_eq!("add:10".parse(), Ok(KeyHash::Add(10)));
+    assert!("mul:3".parse::<KeyHash>().is_err());
+    let hashes = [KeyHash::Identity, KeyHash::Xor(0x9e3779b97f4a7c15), KeyHash::Add(u64::MAX - 41)];
+    for &policy in &[SizePolicy::Mask, SizePolicy::Prime] {
+        for &hash in &hashes {
+            for &(min, max) in &[(0, 1000000000000000000), (-5000000000000, 5000000000000)] {
+                let set = hash_table_attack::pbds_keys(100000, min, max, policy, hash).expect("keys not found");
+                assert_eq!(set.keys.len(), 100000);
+                assert!(set.bucket_counts.contains(&hash_table_attack::pbds_size(100000, policy).unwrap()));
+                let distinct: std::collections::HashSet<_> = set.keys.iter().collect();
+                assert_eq!(distinct.len(), 100000);
+                for &key in &set.keys {
+                    assert!(min <= key && key <= max);
+                    let h = match hash {
+                        KeyHash::Identity => key as u64,
+                        KeyHash::Xor(c) => key as u64 ^ c,
+                        KeyHash::Add(c) => (key as u64).wrapping_add(c),
+                    };
+                    assert!(set.bucket_counts.iter().all(|&b| h.is_multiple_of(b)));
+                }
+            }
+        }
+    }
+    assert!(hash_table_attack::pbds_keys(100000, 0, 1000000, SizePolicy::Prime, KeyHash::Identity).is_err());
+}
+
+#[test]
+fn hash_map_strings() {
+    let java = |s: &str| s.chars().fold(0i32, |h, c| h.wrapping_mul(31).wrapping_add(c as i32));
+    let hashes = preset::find("java-string").unwrap().hash_spec().hashes;
+    let letters: Vec<String> = ('a'..='z').chain('A'..='Z').map(|c| c.to_string()).collect();
+    let mut ctx = Context::new(42);
+    for alphabet in &[letters, default_alphabet()] {
+        let set = hash_table_attack::equal_hash_strings(&hashes, 10000, 1000, alphabet, &mut ctx).expect("not found");
+        assert_eq!(set.strings.len(), 10000);
+        let distinct: std::collections::HashSet<_> = set.strings.iter().collect();
+        assert_eq!(distinct.len(), 10000);
+        for s in &set.strings {
+            assert!(s.chars().count() <= 1000);
+            assert_eq!(java(s), java(&set.strings[0]));
+        }
+    }
+    let result = hash_table_attack::equal_hash_strings(&hashes, 10000, 10, &default_alphabet(), &mut ctx);
+    assert!(matches!(result, Err(AttackError::LengthTooShort { .. })));
+    let alphabet: Vec<String> = ["A", "B", "a", "b"].iter().map(|s| s.to_string()).collect();
+    let run = || hash_table_attack::equal_hash_strings(&hashes, 5, 1000, &alphabet, &mut Context::new(1));
+    assert_eq!(run(), run());
+}
+
+#[test]
+fn refuse_broken_collision() {
+    // The sums of "ab" and "`c" are equal, but uppercase only changes the letters.
+    let target = HashSpec::new(vec![PolynomialHash::new(1, 1000000007)]);
+    let coll = Collision {
+        first: "ab".to_string(),
+        second: "`c".to_string(),
+        hashes: target.evaluate("ab"),
+        attack: "birthday",
+        length: 2,
+        depths: vec![2],
+        steps: 0,
+        elapsed: Duration::default(),
+    };
+    let matches = app().get_matches_from(["antihash", "presets"]);
+    assert_eq!(print_collision(&matches, &target, &target, coll.clone()), 0);
+    let matches = app().get_matches_from(["antihash", "--uppercase", "presets"]);
+    assert_eq!(print_collision(&matches, &target, &target, coll), 1);
+
+    let options = HashOptions::from_matches(&matches);
+    assert_eq!(overflow_check(100, &options).hashes.len(), 3);
+    let coll = OverflowAttack::default().run(&HashSpec::default(), &mut Context::default()).unwrap();
+    assert_eq!(print_collision(&matches, &HashSpec::default(), &overflow_check(64, &options), coll), 0);
+}
+
+#[test]
+fn verify_strings() {
+    let dir = std::env::temp_dir();
+    let write = |name: &str, content: &str| {
+        let path = dir.join(format!("antihash-verify-{}-{}", process::id(), name));
+        fs::write(&path, content).unwrap();
+        path.to_str().unwrap().to_string()
+    };
+    // Base 1 sums the characters, "ab" and "`c" have the same sum.
+    let (first, second, other) = (write("first", "ab\n"), write("second", "`c\r\n"), write("other", "ac"));
+    for &(fi, se, code) in &[(&first, &second, 0), (&first, &first, 1), (&first, &other, 1)] {
+        let matches = app().get_matches_from(["antihash", "verify", "1", "1000000007", "--", fi, se]);
+        let options = HashOptions::from_matches(&matches);
+        assert_eq!(verify(matches.subcommand_matches("verify").unwrap(), &options), code);
+    }
+    for path in &[first, second, other] {
+        fs::remove_file(path).unwrap();
+    }
+    assert_eq!(first_lines("ab\r\n`c\r\n").unwrap(), ("ab".to_string(), "`c".to_string()));
+    assert_eq!(first_lines("ab\n`c").unwrap(), ("ab".to_string(), "`c".to_string()));
+    assert!(first_lines("ab\n").is_err());
+    assert!(first_lines("").is_err());
+}
@@ -1,10 +1,198 @@
-pub fn find_collision(length: usize) -> Option<(String, String)> {
+use crate::attack::{check_alphabet, Attack, AttackError, Collision, Context};
+use crate::hash::{check_arithmetic, recheck, Direction, HashSpec};
+use std::time::Instant;
+
+/// Attack against hashes computed modulo `2^bits`.
+///
+/// The bases and directions of the target are taken into account, their moduli must be `2^bits`,
+/// or `0` (i.e. `2^64`) when `bits` is at least 64, which then only checks the collision modulo
+/// `2^64`. Odd bases are attacked with a Thue–Morse pair, even bases by padding the strings
+/// with equal characters, so that the difference gets multiplied by a large power of the base.
+#[derive(Clone, Debug)]
+pub struct OverflowAttack {
+    /// Exact length of the strings, the shortest valid length is used if missing.
+    pub length: Option<usize>,
+    /// Size of the machine word, the hash is computed modulo `2^bits`.
+    pub bits: u32,
+    /// The two words used to build the strings, they must have the same length.
+    pub alphabet: Vec<String>,
+    /// Character appended to both strings to reach `length`, the first character of the
+    /// alphabet if missing.
+    pub filler: Option<char>,
+}
+
+impl Default for OverflowAttack {
+    fn default() -> OverflowAttack {
+        OverflowAttack {
+            length: None,
+            bits: 64,
+            alphabet: vec!["a".to_string(), "b".to_string()],
+            filler: None,
+        }
+    }
+}
+
+impl Attack for OverflowAttack {
+    fn name(&self) -> &'static str {
+        "overflow"
+    }
+
+    fn run(&self, target: &HashSpec, _ctx: &mut Context) -> Result<Collision, AttackError> {
+        check_alphabet(&self.alphabet)?;
+        check_arithmetic(&target.hashes)?;
+        if self.alphabet.len() != 2 {
+            return Err(AttackError::InvalidParameter("the overflow attack needs exactly two words".to_string()));
+        }
+        let layout = self.layout(target);
+        let min_length = self.min_length(target);
+        let length = self.length.unwrap_or(min_length);
+        if length < min_length {
+            return Err(AttackError::LengthTooShort { min_length });
+        }
+        let modulus = if self.bits < 64 { 1 << self.bits } else { 0 };
+        if let Some(hash) = target.hashes.iter().find(|h| h.modulus != modulus) {
+            let reason = format!("the overflow attack needs the modulus 2^{}, not {}", self.bits.min(64), hash.modulus);
+            return Err(AttackError::InvalidHash(reason));
+        }
+        let filler = self.filler.unwrap_or_else(|| self.alphabet[0].chars().next().unwrap());
+        let coll = thue_morse(&self.alphabet, layout.blocks, layout.prefix, length, filler)?;
+        recheck(&target.hashes, coll)
+    }
+}
+
+/// Shape of the strings: `prefix` filler characters, a Thue–Morse pair of `blocks` words and
+/// at least `suffix` filler characters.
+struct Layout {
+    prefix: usize,
+    blocks: usize,
+    suffix: usize,
+}
+
+impl OverflowAttack {
+    fn layout(&self, target: &HashSpec) -> Layout {
+        if target.hashes.is_empty() {
+            return Layout {
+                prefix: 0,
+                blocks: min_length(self.bits),
+                suffix: 0,
+            };
+        }
+        let word_len = self.alphabet[0].chars().count();
+        let mut layout = Layout {
+            prefix: 0,
+            blocks: 1,
+            suffix: 0,
+        };
+        for hash in &target.hashes {
+            if hash.base % 2 == 1 {
+                layout.blocks = layout.blocks.max(min_blocks(hash.base, word_len, self.bits));
+            } else {
+                let padding = min_padding(hash.base, self.bits);
+                match hash.direction {
+                    Direction::LeftToRight => layout.suffix = layout.suffix.max(padding),
+                    Direction::RightToLeft => layout.prefix = layout.prefix.max(padding),
+                }
+            }
+        }
+        layout
+    }
+
+    /// Returns the length of the shortest strings colliding under every hash of `target`.
+    pub fn min_length(&self, target: &HashSpec) -> usize {
+        let layout = self.layout(target);
+        layout.prefix + layout.blocks * self.alphabet[0].chars().count() + layout.suffix
+    }
+}
+
+fn thue_morse_blocks(mut power: u32, step: u32, bits: u32) -> usize {
+    let mut order = 1;
+    while power < bits {
+        power = power.saturating_add(step + order - 1);
+        order += 1;
+    }
+    1 << order
+}
+
+/// Returns the minimal number of words of a Thue–Morse pair colliding modulo `2^bits` for every
+/// odd base.
+///
+/// The difference of the hashes of the pair of order `k` is `(B - 1)(B^2 - 1)...(B^(2^(k-1)) - 1)`
+/// and, for an odd `B`, `2` divides `B - 1` and `2^(i+2)` divides `B^(2^i) - 1` when `i > 0`.
+pub fn min_length(bits: u32) -> usize {
+    thue_morse_blocks(1, 3, bits)
+}
+
+/// Returns the minimal number of words of a Thue–Morse pair colliding modulo `2^bits` for the odd
+/// base `base`, when every word has `word_len` characters.
+pub fn min_blocks(base: u64, word_len: usize, bits: u32) -> usize {
+    let b = (base as u128).wrapping_pow(word_len as u32);
+    let valuation = |x: u128| x.trailing_zeros();
+    thue_morse_blocks(valuation(b.wrapping_sub(1)), valuation(b.wrapping_mul(b).wrapping_sub(1)), bits)
+}
+
+/// Returns how many equal characters at the end (or at the start, when hashing from the right)
+/// make any two strings collide modulo `2^bits` for the even base `base`, since `base^padding`
+/// is a multiple of `2^bits`.
+pub fn min_padding(base: u64, bits: u32) -> usize {
+    if base == 0 {
+        return 1;
+    }
+    let twos = base.trailing_zeros();
+    bits.div_ceil(twos) as usize
+}
+
+/// Builds the first `blocks` words of the Thue–Morse sequence over the two words of `alphabet`
+/// and its complement, preceded by `prefix` characters `filler` and followed by as many as
+/// needed to reach `length` characters.
+///
+/// Padding both strings with the same characters either adds the same value to both hashes or
+/// multiplies their difference by a power of the base, so the collision is preserved.
+pub fn thue_morse(
+    alphabet: &[String], blocks: usize, prefix: usize, length: usize, filler: char,
+) -> Result<Collision, AttackError> {
+    let start = Instant::now();
+    check_alphabet(alphabet)?;
+    if alphabet.len() != 2 {
+        return Err(AttackError::InvalidParameter("the overflow attack needs exactly two words".to_string()));
+    }
+    let min_length = prefix + blocks * alphabet[0].chars().count();
+    if length < min_length {
+        return Err(AttackError::LengthTooShort { min_length });
+    }
     let mut fi = String::with_capacity(length);
     let mut se = String::with_capacity(length);
-    for i in 0..length {
-        let p = (i.count_ones() % 2) as u8;
-        fi.push(char::from(97 + p));
-        se.push(char::from(98 - p));
+    for _ in 0..prefix {
+        fi.push(filler);
+        se.push(filler);
+    }
+    for i in 0..blocks {
+        let p = (i.count_ones() % 2) as usize;
+        fi.push_str(&alphabet[p]);
+        se.push_str(&alphabet[1 - p]);
+    }
+    for _ in min_length..length {
+        fi.push(filler);
+        se.push(filler);
+    }
+    Ok(Collision {
+        first: fi,
+        second: se,
+        hashes: Vec::new(),
+        attack: "overflow",
+        length,
+        depths: Vec::new(),
+        steps: 0,
+        elapsed: start.elapsed(),
+    })
+}
+
+/// Returns the Thue–Morse pair over `a` and `b` of `length` characters, which must be a power of
+/// two. It collides modulo `2^bits` for every odd base when `length` is at least
+/// [`min_length(bits)`](min_length).
+pub fn find_collision(length: usize) -> Result<Collision, AttackError> {
+    if length < 2 || !length.is_power_of_two() {
+        let reason = "strings must have a power of two characters, at least two";
+        return Err(AttackError::InvalidParameter(reason.to_string()));
     }
-    Some((fi, se))
+    thue_morse(&["a".to_string(), "b".to_string()], length, 0, length, 'a')
 }
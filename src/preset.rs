@@ -0,0 +1,76 @@
+use crate::hash::{Arithmetic, HashSpec, PolynomialHash};
+
+/// Named hash used in contests or libraries.
+pub struct Preset {
+    pub name: &'static str,
+    pub description: &'static str,
+    build: fn() -> HashSpec,
+}
+
+impl Preset {
+    /// Returns the hashes computed by the preset.
+    pub fn hash_spec(&self) -> HashSpec {
+        (self.build)()
+    }
+}
+
+fn java_string() -> HashSpec {
+    let mut hash = PolynomialHash::new(31, 0);
+    hash.arithmetic = Arithmetic::Signed {
+        bits: 32,
+        per_step: false,
+    };
+    HashSpec::new(vec![hash])
+}
+
+fn djb2() -> HashSpec {
+    let mut hash = PolynomialHash::new(33, 1 << 32);
+    hash.initial = 5381;
+    HashSpec::new(vec![hash])
+}
+
+fn sdbm() -> HashSpec {
+    HashSpec::new(vec![PolynomialHash::new(65599, 1 << 32)])
+}
+
+fn double_131() -> HashSpec {
+    HashSpec::new(vec![PolynomialHash::new(131, 1000000007), PolynomialHash::new(131, 1000000009)])
+}
+
+fn mersenne61() -> HashSpec {
+    HashSpec::new(vec![PolynomialHash::new(131, (1 << 61) - 1)])
+}
+
+/// Every known preset.
+pub const PRESETS: &[Preset] = &[
+    Preset {
+        name: "java-string",
+        description: "Java String.hashCode, signed 32-bit overflow",
+        build: java_string,
+    },
+    Preset {
+        name: "djb2",
+        description: "Bernstein's djb2, 32-bit overflow starting from 5381",
+        build: djb2,
+    },
+    Preset {
+        name: "sdbm",
+        description: "sdbm database library hash, 32-bit overflow",
+        build: sdbm,
+    },
+    Preset {
+        name: "double-131",
+        description: "Double hash with base 131 modulo 1e9+7 and 1e9+9",
+        build: double_131,
+    },
+    Preset {
+        name: "mersenne61",
+        description: "Hash with base 131 modulo the Mersenne prime 2^61-1",
+        build: mersenne61,
+    },
+];
+
+/// Returns the preset called `name`.
+pub fn find(name: &str) -> Option<&'static Preset> {
+    PRESETS.iter().find(|p| p.name == name)
+}
@@ -1,6 +1,45 @@
+use crate::attack::{check_alphabet, Attack, AttackError, Collision, Context, Progress};
+use crate::hash::{linearize_all, recheck, Direction, HashSpec, PolynomialHash};
 use binary_heap_plus::{BinaryHeap, MinComparator};
 use rand::Rng;
 use std::collections::{HashSet, VecDeque};
+use std::mem::size_of;
+use std::time::Instant;
+
+/// Tree attack, feasible for large moduli.
+#[derive(Clone, Debug)]
+pub struct TreeAttack {
+    /// Maximum number of nodes kept for each subtree.
+    pub cluster_size: usize,
+    /// Words used to build the strings, they must have the same length.
+    pub alphabet: Vec<String>,
+    /// Cancels consecutive hashes with coprime moduli in a single tree, see [`find_joint_collision`].
+    pub joint: bool,
+}
+
+impl Default for TreeAttack {
+    fn default() -> TreeAttack {
+        TreeAttack {
+            cluster_size: 100000,
+            alphabet: crate::default_alphabet(),
+            joint: false,
+        }
+    }
+}
+
+impl Attack for TreeAttack {
+    fn name(&self) -> &'static str {
+        "tree"
+    }
+
+    fn run(&self, target: &HashSpec, ctx: &mut Context) -> Result<Collision, AttackError> {
+        if self.joint {
+            find_joint_collision(&target.hashes, self.cluster_size, self.alphabet.clone(), ctx)
+        } else {
+            find_collision(&target.hashes, self.cluster_size, self.alphabet.clone(), ctx)
+        }
+    }
+}
 
 #[derive(Clone)]
 struct TreeAttackInternalNode {
@@ -40,14 +79,7 @@ impl<'a> TreeAttackNode<'a> {
         })
     }
 
-    fn new_leaf(
-        idx: usize, word1: &'a String, word2: &'a String, base: i128, module: i128, pot: i128,
-    ) -> TreeAttackNode<'a> {
-        let mut hash = 0;
-        for (c1, c2) in word1.chars().zip(word2.chars()) {
-            hash = (hash * base + c1 as i128 - c2 as i128 + module) % module;
-        }
-        let sum = hash * pot % module;
+    fn new_leaf(idx: usize, word1: &'a String, word2: &'a String, sum: i128) -> TreeAttackNode<'a> {
         TreeAttackNode::Leaf(TreeAttackLeafNode {
             sum,
             idx,
@@ -64,57 +96,89 @@ impl<'a> TreeAttackNode<'a> {
     }
 }
 
-struct TreeAttack<'a> {
+struct TreeSearch<'a> {
     alphabet: &'a Vec<String>,
     word_len: usize,
-    base: i128,
-    module: i128,
+    /// Hashes cancelled together, their moduli are pairwise coprime with a product below `2^64`.
+    hashes: &'a [PolynomialHash],
+    /// For each hash, the product of the previous moduli and its inverse modulo the hash modulus.
+    crt: Vec<(u128, u128)>,
     cluster_size: usize,
     tree: Vec<Vec<TreeAttackNode<'a>>>,
     heap: BinaryHeap<(i128, usize, usize, bool), MinComparator>,
     added: HashSet<(usize, usize, bool)>,
+    pops: u64,
+    depth: usize,
+    best: Option<i128>,
 }
 
-impl<'a> TreeAttack<'a> {
+impl<'a> TreeSearch<'a> {
     fn new(
-        base: u64, module: u64, cluster_size: usize, alphabet: &'a Vec<String>,
-    ) -> TreeAttack<'a> {
-        TreeAttack {
-            alphabet: alphabet,
-            word_len: alphabet[0].len(),
-            base: base as i128,
-            module: module as i128,
-            cluster_size: cluster_size,
+        hashes: &'a [PolynomialHash], cluster_size: usize, alphabet: &'a Vec<String>,
+    ) -> TreeSearch<'a> {
+        let mut crt = Vec::with_capacity(hashes.len());
+        let mut product = 1;
+        for hash in hashes {
+            let modulus = hash.modulus as u128;
+            crt.push((product, if modulus == 0 { 1 } else { inverse(product % modulus, modulus) }));
+            product *= modulus;
+        }
+        TreeSearch {
+            alphabet,
+            word_len: alphabet[0].chars().count(),
+            hashes,
+            crt,
+            cluster_size,
             tree: Vec::new(),
-            heap: BinaryHeap::with_capacity_min(3 * cluster_size),
-            added: HashSet::with_capacity(5 * cluster_size),
+            heap: BinaryHeap::new_min(),
+            added: HashSet::new(),
+            pops: 0,
+            depth: 0,
+            best: None,
+        }
+    }
+
+    /// Value of the leaf putting `word1` and `word2` at the positions weighted by `pots`, combined
+    /// over all the hashes with the chinese remainder theorem.
+    fn leaf_value(&self, word1: &str, word2: &str, pots: &[u64]) -> i128 {
+        let mut value = 0;
+        for ((hash, &pot), &(product, inv)) in self.hashes.iter().zip(pots).zip(&self.crt) {
+            let v = hash.mul(hash.difference(word1, word2), pot) as u128;
+            if hash.modulus == 0 {
+                return v as i128;
+            }
+            let modulus = hash.modulus as u128;
+            let t = (v + modulus - value % modulus) % modulus * inv % modulus;
+            value += product * t;
         }
+        value as i128
     }
 
     fn init_attack(&mut self, len: usize) {
         self.tree.resize(2 * len, Vec::with_capacity(self.cluster_size));
-        let mut pot = 1i128;
         for i in (0..len).rev() {
+            let pots: Vec<u64> = self
+                .hashes
+                .iter()
+                .map(|hash| {
+                    let shift = match hash.direction {
+                        Direction::LeftToRight => len - 1 - i,
+                        Direction::RightToLeft => i,
+                    };
+                    hash.pow(shift * self.word_len)
+                })
+                .collect();
             self.tree[i].clear();
             for a in 0..self.alphabet.len() {
                 for b in 0..self.alphabet.len() {
-                    if a != b {
-                        self.tree[i + len].push(TreeAttackNode::new_leaf(
-                            i,
-                            &self.alphabet[a],
-                            &self.alphabet[b],
-                            self.base,
-                            self.module,
-                            pot,
-                        ))
+                    if self.alphabet[a] != self.alphabet[b] {
+                        let sum = self.leaf_value(&self.alphabet[a], &self.alphabet[b], &pots);
+                        self.tree[i + len].push(TreeAttackNode::new_leaf(i, &self.alphabet[a], &self.alphabet[b], sum))
                     }
                 }
             }
             self.tree[i + len].sort_unstable_by_key(|k| k.get_sum());
             self.tree[i + len].dedup_by_key(|k| k.get_sum());
-            for _ in 0..self.word_len {
-                pot = pot * self.base % self.module;
-            }
         }
     }
 
@@ -126,10 +190,11 @@ impl<'a> TreeAttack<'a> {
         (self.tree[l][pl].get_sum() - self.tree[r][pr].get_sum()).abs()
     }
 
-    fn run_phase(&mut self, p: usize) -> Option<usize> {
+    fn run_phase(&mut self, p: usize, ctx: &mut Context) -> Result<Option<usize>, AttackError> {
         let z = 1 << p;
         self.tree[2 * z..4 * z].sort_unstable_by_key(|c| c[0].get_sum());
         for i in z..2 * z {
+            ctx.check_time()?;
             self.heap.clear();
             self.added.clear();
             let (l, r) = (2 * i, 2 * i + 1);
@@ -151,6 +216,7 @@ impl<'a> TreeAttack<'a> {
             let mut last_sum = -1;
             while self.tree[i].len() < self.cluster_size {
                 if let Some((s, pl, pr, b)) = self.heap.pop() {
+                    self.pops += 1;
                     if b {
                         if s != last_sum {
                             self.tree[i].push(TreeAttackNode::new_internal(s, i, false, false, pl, pr));
@@ -191,17 +257,32 @@ impl<'a> TreeAttack<'a> {
                         }
                     }
                     if s == 0 {
-                        return Some(i);
+                        return Ok(Some(i));
+                    }
+                    if self.best.is_none_or(|b| s < b) {
+                        self.best = Some(s);
+                    }
+                    // The root only matters if it reaches zero, and the smallest sum comes first.
+                    if p == 0 {
+                        break;
                     }
                 } else {
                     break;
                 }
             }
+            ctx.report(Progress::Tree {
+                depth: self.depth,
+                phase: p,
+                cluster: i - z,
+                clusters: z,
+                fill: self.tree[i].len(),
+                best: self.best,
+            });
         }
-        None
+        Ok(None)
     }
 
-    fn construct_solution(&mut self, len: usize, idx: usize) -> (String, String) {
+    fn construct_solution(&mut self, len: usize, idx: usize, ctx: &mut Context) -> (String, String) {
         let mut words = Vec::new();
         words.resize(len, None);
         let mut queue = VecDeque::with_capacity(len);
@@ -223,7 +304,6 @@ impl<'a> TreeAttack<'a> {
                 }
             };
         }
-        let mut rng = rand::thread_rng();
         let cap = len * self.word_len;
         let mut fi = String::with_capacity(cap);
         let mut se = String::with_capacity(cap);
@@ -232,51 +312,154 @@ impl<'a> TreeAttack<'a> {
                 fi.push_str(w1);
                 se.push_str(w2);
             } else {
-                let idx = rng.gen_range(0, self.alphabet.len());
+                let idx = ctx.rng.gen_range(0, self.alphabet.len());
                 fi.push_str(&self.alphabet[idx]);
                 se.push_str(&self.alphabet[idx]);
             }
         }
-        return (fi, se);
+        (fi, se)
     }
 
-    fn try_attack(&mut self, p: usize) -> Option<(String, String)> {
+    fn required_memory(&self, len: usize) -> usize {
+        let tree = 2 * len * self.cluster_size * size_of::<TreeAttackNode>();
+        let heap = 3 * self.cluster_size * size_of::<(i128, usize, usize, bool)>();
+        let added = 5 * self.cluster_size * (size_of::<(usize, usize, bool)>() + 1);
+        tree + heap + added
+    }
+
+    fn try_attack(&mut self, p: usize, ctx: &mut Context) -> Result<Option<(String, String)>, AttackError> {
         let len = 1 << p;
+        ctx.check_memory(self.required_memory(len))?;
+        self.heap.reserve(3 * self.cluster_size);
+        self.added.reserve(5 * self.cluster_size);
+        self.depth = p;
+        self.best = None;
         self.init_attack(len);
         for i in (0..p).rev() {
-            if let Some(idx) = self.run_phase(i) {
-                return Some(self.construct_solution(len, idx));
+            if let Some(idx) = self.run_phase(i, ctx)? {
+                return Ok(Some(self.construct_solution(len, idx, ctx)));
             }
         }
-        None
+        Ok(None)
     }
 }
 
+const MIN_DEPTH: usize = 3;
+const MAX_DEPTH: usize = 12;
+
+struct SingleCollision {
+    first: String,
+    second: String,
+    depth: usize,
+    pops: u64,
+}
+
 fn find_single_collision(
-    base: u64, module: u64, cluster_size: usize, alphabet: &Vec<String>,
-) -> Option<(String, String)> {
-    let mut attack = TreeAttack::new(base, module, cluster_size, alphabet);
-    for i in 3..12 {
-        let coll = attack.try_attack(i);
-        if coll.is_some() {
-            return coll;
+    hashes: &[PolynomialHash], cluster_size: usize, alphabet: &Vec<String>, ctx: &mut Context,
+) -> Result<SingleCollision, AttackError> {
+    let mut attack = TreeSearch::new(hashes, cluster_size, alphabet);
+    for i in MIN_DEPTH..MAX_DEPTH {
+        if let Some((first, second)) = attack.try_attack(i, ctx)? {
+            return Ok(SingleCollision {
+                first,
+                second,
+                depth: i,
+                pops: attack.pops,
+            });
         }
     }
-    None
+    Err(AttackError::LengthLimitExceeded {
+        attack: "tree",
+        max_length: 1 << (MAX_DEPTH - 1),
+    })
 }
 
-pub fn find_collision(
-    bases: Vec<u64>, modules: Vec<u64>, cluster_size: usize, init_alphabet: Vec<String>,
-) -> Option<(String, String)> {
-    let mut alphabet = init_alphabet;
-    for (&b, &m) in bases.iter().zip(modules.iter()) {
-        if let Some((fi, se)) = find_single_collision(b, m, cluster_size, &alphabet) {
-            alphabet = vec![fi, se];
-        } else {
-            return None;
+fn inverse(a: u128, modulus: u128) -> u128 {
+    let (mut r0, mut r1) = (modulus as i128, a as i128);
+    let (mut t0, mut t1) = (0i128, 1i128);
+    while r1 != 0 {
+        let q = r0 / r1;
+        (r0, r1) = (r1, r0 - q * r1);
+        (t0, t1) = (t1, t0 - q * t1);
+    }
+    t0.rem_euclid(modulus as i128) as u128
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Splits `hashes` into runs of consecutive hashes whose moduli are pairwise coprime, with a
+/// product below `2^64`. A modulus `2^64` always stays alone.
+fn joint_groups(hashes: &[PolynomialHash]) -> Vec<&[PolynomialHash]> {
+    let mut groups = Vec::new();
+    let (mut start, mut product) = (0, 1u64);
+    for (i, hash) in hashes.iter().enumerate() {
+        let fits = hash.modulus != 0 && gcd(product, hash.modulus) == 1 && product.checked_mul(hash.modulus).is_some();
+        if i > start && !(fits && product != 0) {
+            groups.push(&hashes[start..i]);
+            start = i;
+            product = 1;
         }
+        product = product.wrapping_mul(hash.modulus);
+    }
+    if start < hashes.len() {
+        groups.push(&hashes[start..]);
+    }
+    groups
+}
+
+/// Finds a collision for each group of hashes in turn, using the previous collision as alphabet.
+fn chain_collisions(
+    groups: &[&[PolynomialHash]], cluster_size: usize, init_alphabet: Vec<String>, ctx: &mut Context,
+) -> Result<Collision, AttackError> {
+    let start = Instant::now();
+    check_alphabet(&init_alphabet)?;
+    if cluster_size == 0 {
+        return Err(AttackError::InvalidParameter("clusters must not be empty".to_string()));
+    }
+    let mut alphabet = init_alphabet;
+    let (mut depths, mut steps) = (Vec::new(), 0);
+    for group in groups {
+        let coll = find_single_collision(group, cluster_size, &alphabet, ctx)?;
+        depths.extend(group.iter().map(|_| coll.depth));
+        steps += coll.pops;
+        alphabet = vec![coll.first, coll.second];
     }
     let se = alphabet.remove(1);
     let fi = alphabet.remove(0);
-    Some((fi, se))
+    let hashes = groups.iter().flat_map(|g| g.iter());
+    Ok(Collision {
+        hashes: hashes.map(|h| h.evaluate(&fi)).collect(),
+        attack: "tree",
+        length: fi.chars().count(),
+        first: fi,
+        second: se,
+        depths,
+        steps,
+        elapsed: start.elapsed(),
+    })
+}
+
+pub fn find_collision(
+    hashes: &[PolynomialHash], cluster_size: usize, init_alphabet: Vec<String>, ctx: &mut Context,
+) -> Result<Collision, AttackError> {
+    let linear = linearize_all(hashes)?;
+    let groups: Vec<_> = linear.iter().map(std::slice::from_ref).collect();
+    recheck(hashes, chain_collisions(&groups, cluster_size, init_alphabet, ctx)?)
+}
+
+/// Same as [`find_collision`], but consecutive hashes whose moduli are coprime with a product
+/// below `2^64` are cancelled in a single tree, whose leaves hold the combination of their values
+/// given by the chinese remainder theorem. Double hashing modulo two primes near `10^9` then needs
+/// a single tree instead of two chained ones, which keeps the strings much shorter.
+pub fn find_joint_collision(
+    hashes: &[PolynomialHash], cluster_size: usize, init_alphabet: Vec<String>, ctx: &mut Context,
+) -> Result<Collision, AttackError> {
+    let linear = linearize_all(hashes)?;
+    recheck(hashes, chain_collisions(&joint_groups(&linear), cluster_size, init_alphabet, ctx)?)
 }